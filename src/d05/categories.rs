@@ -0,0 +1,265 @@
+use super::*;
+
+use std::fmt;
+
+// The maps form a graph with a category at each node and a map along each edge. The puzzle input
+// is a single chain from seed to location, but nothing in the format stops a category having
+// several maps out of it, so any two categories can be asked about as long as there's exactly one
+// way to get from one to the other.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError<'a> {
+    /// No sequence of maps goes from src to dst
+    NoRoute { src: &'a str, dst: &'a str },
+    /// More than one sequence of maps goes from src to dst, and they might not agree
+    Ambiguous { src: &'a str, dst: &'a str },
+    /// The maps go round in a loop through these categories, starting and ending at the same one
+    Cycle(Vec<&'a str>),
+}
+
+impl<'a> fmt::Display for RouteError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::NoRoute { src, dst } => write!(f, "no maps go from {} to {}", src, dst),
+            RouteError::Ambiguous { src, dst } => {
+                write!(
+                    f,
+                    "more than one route of maps goes from {} to {}",
+                    src, dst
+                )
+            }
+            RouteError::Cycle(categories) => {
+                write!(f, "the maps go round in a loop {}", categories.join(" -> "))
+            }
+        }
+    }
+}
+
+impl<'a> Error for RouteError<'a> {}
+
+/// Counts the routes from each category to dst, stopping at two since that's already ambiguous
+struct Search<'m, 'a> {
+    maps: &'m AllMaps<'a>,
+    dst: &'a str,
+    routes: HashMap<&'a str, usize>,
+    /// The categories being searched from, to spot loops
+    stack: Vec<&'a str>,
+}
+
+impl<'m, 'a> Search<'m, 'a> {
+    fn count(&mut self, category: &'a str) -> Result<usize, RouteError<'a>> {
+        if category == self.dst {
+            return Ok(1);
+        }
+        if let Some(start) = self.stack.iter().position(|&other| other == category) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(category);
+            return Err(RouteError::Cycle(cycle));
+        }
+        if let Some(&routes) = self.routes.get(category) {
+            return Ok(routes);
+        }
+
+        self.stack.push(category);
+        let mut routes = 0;
+        for map in self.maps.get(category).into_iter().flatten() {
+            routes = (routes + self.count(map.dst_type)?).min(2);
+        }
+        self.stack.pop();
+        self.routes.insert(category, routes);
+        Ok(routes)
+    }
+}
+
+/// The maps to go through, in order, to get from src to dst. Empty if they're the same category
+pub fn route<'m, 'a>(
+    maps: &'m AllMaps<'a>,
+    src: &'a str,
+    dst: &'a str,
+) -> Result<Vec<&'m Map<'a>>, RouteError<'a>> {
+    let mut search = Search {
+        maps,
+        dst,
+        routes: HashMap::new(),
+        stack: vec![],
+    };
+    match search.count(src)? {
+        0 => return Err(RouteError::NoRoute { src, dst }),
+        1 => (),
+        _ => return Err(RouteError::Ambiguous { src, dst }),
+    }
+
+    // every category on the way has exactly one route, so follow the maps that lead to one
+    let mut route = vec![];
+    let mut category = src;
+    while category != dst {
+        let map = maps[category]
+            .iter()
+            .find(|map| map.dst_type == dst || search.routes.get(map.dst_type) == Some(&1))
+            .unwrap();
+        route.push(map);
+        category = map.dst_type;
+    }
+    Ok(route)
+}
+
+/// The maps from src to dst composed into a single map
+pub fn compose_between<'a>(
+    maps: &AllMaps<'a>,
+    src: &'a str,
+    dst: &'a str,
+) -> Result<Map<'a>, RouteError<'a>> {
+    let identity = Map::new(src, src, vec![], vec![]);
+    Ok(route(maps, src, dst)?
+        .into_iter()
+        .fold(identity, |composed, map| composed.compose(map)))
+}
+
+/// Something that can be sent through a map: a single value, or a range of them
+pub trait Transform {
+    type Output;
+
+    fn transform(&self, map: &Map) -> Self::Output;
+}
+
+impl Transform for u64 {
+    type Output = u64;
+
+    fn transform(&self, map: &Map) -> u64 {
+        map.transform(*self)
+    }
+}
+
+impl Transform for Range {
+    type Output = RangeSet;
+
+    fn transform(&self, map: &Map) -> RangeSet {
+        transform_range(map, self)
+    }
+}
+
+/// Send a value or range from category src to category dst, e.g. for a one off --between query.
+/// The route is found and composed on every call
+pub fn transform_between<'a, T: Transform>(
+    maps: &AllMaps<'a>,
+    src: &'a str,
+    dst: &'a str,
+    val: T,
+) -> Result<T::Output, RouteError<'a>> {
+    Ok(val.transform(&compose_between(maps, src, dst)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    //   seed -> soil -> water -> location
+    //      \                   /
+    //       -> fertilizer ----
+    //              \
+    //               -> light
+    const FORKED: &str = indoc! {"
+        seeds: 1
+
+        seed-to-soil map:
+        10 0 5
+
+        soil-to-water map:
+        100 10 5
+
+        water-to-location map:
+        0 100 10
+
+        seed-to-fertilizer map:
+        50 0 10
+
+        fertilizer-to-light map:
+        20 50 10
+
+        fertilizer-to-location map:
+        7 50 1
+    "};
+
+    #[test]
+    fn test_route() {
+        let (_, (_, maps)) = all_consuming(parse)(FORKED).unwrap();
+
+        fn types<'a>(route: Vec<&Map<'a>>) -> Vec<(&'a str, &'a str)> {
+            route
+                .into_iter()
+                .map(|map| (map.src_type, map.dst_type))
+                .collect()
+        }
+        assert_eq!(
+            types(route(&maps, "soil", "location").unwrap()),
+            vec![("soil", "water"), ("water", "location")]
+        );
+        assert_eq!(
+            types(route(&maps, "seed", "light").unwrap()),
+            vec![("seed", "fertilizer"), ("fertilizer", "light")]
+        );
+        assert_eq!(types(route(&maps, "water", "water").unwrap()), vec![]);
+
+        assert_eq!(
+            route(&maps, "seed", "location").unwrap_err(),
+            RouteError::Ambiguous {
+                src: "seed",
+                dst: "location"
+            }
+        );
+        assert_eq!(
+            route(&maps, "light", "seed").unwrap_err(),
+            RouteError::NoRoute {
+                src: "light",
+                dst: "seed"
+            }
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let input = indoc! {"
+            seeds: 1
+
+            seed-to-soil map:
+            0 0 1
+
+            soil-to-water map:
+            0 0 1
+
+            water-to-soil map:
+            0 0 1
+        "};
+        let (_, (_, maps)) = all_consuming(parse)(input).unwrap();
+        let err = route(&maps, "seed", "location").unwrap_err();
+        assert_eq!(err, RouteError::Cycle(vec!["soil", "water", "soil"]));
+        assert_eq!(
+            err.to_string(),
+            "the maps go round in a loop soil -> water -> soil"
+        );
+        // the loop is after water, so it doesn't matter
+        assert!(route(&maps, "seed", "water").is_ok());
+    }
+
+    #[test]
+    fn test_transform_between() {
+        let (_, (_, maps)) = all_consuming(parse)(FORKED).unwrap();
+
+        // 2 -> soil 12 -> water 102 -> location 2
+        assert_eq!(transform_between(&maps, "seed", "soil", 2), Ok(12));
+        assert_eq!(transform_between(&maps, "seed", "water", 2), Ok(102));
+        assert_eq!(transform_between(&maps, "soil", "location", 12), Ok(2));
+        assert_eq!(transform_between(&maps, "fertilizer", "light", 55), Ok(25));
+        assert_eq!(transform_between(&maps, "light", "light", 55), Ok(55));
+
+        // seeds 0 - 5 go through soil 10 - 15, and seeds 10 - 15 are left alone by seed-to-soil,
+        // so both end up at water 100 - 105
+        let ranges = transform_between(&maps, "seed", "water", Range::new(0, 20)).unwrap();
+        assert_eq!(
+            ranges.into_iter().collect::<Vec<_>>(),
+            vec![Range::new(5, 10), Range::new(15, 20), Range::new(100, 105)]
+        );
+        assert!(transform_between(&maps, "seed", "location", 2).is_err());
+    }
+}
@@ -1,7 +1,13 @@
-use itertools::Itertools;
+mod categories;
+mod provenance;
+
+use advent_of_code_2023::ndrange::{Interval, IntervalSet};
+use categories::{compose_between, transform_between, RouteError, Transform};
 use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::time::Instant;
 
 use nom::IResult;
 use nom::{
@@ -12,46 +18,10 @@ use nom::{
     sequence::{terminated, tuple},
 };
 
-// using my own custom range class instead of rust's built in range class so i can implement
-// overlaps(). [start, end)
-#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
-struct Range {
-    start: u64,
-    end: u64,
-}
-
-impl Range {
-    fn new(start: u64, end: u64) -> Range {
-        assert!(start <= end, "start {} is not <= end {}", start, end);
-        Range { start, end }
-    }
-    fn len(&self) -> u64 {
-        self.end - self.start
-    }
-    fn contains(&self, val: u64) -> bool {
-        self.start < val && val < self.end
-    }
-
-    fn overlaps(&self, other: &Range) -> bool {
-        !((self.end <= other.start) || (other.end <= self.start))
-    }
+type Range = Interval<u64>;
+type RangeSet = IntervalSet<u64>;
 
-    fn debug_assert_ranges_disjoint(ranges: &Vec<Range>) {
-        let mut ranges = ranges.clone();
-        ranges.sort();
-        for chunk in ranges.as_slice().chunks_exact(2) {
-            let (range1, range2) = (&chunk[0], &chunk[1]);
-            debug_assert!(
-                range1.end <= range2.start,
-                "seed ranges are not disjoint {:?} {:?}",
-                range1,
-                range2
-            );
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Map<'a> {
     src_type: &'a str,
     dst_type: &'a str,
@@ -68,8 +38,8 @@ impl<'a> Map<'a> {
         dst_ranges: Vec<Range>,
     ) -> Map<'a> {
         // If ranges are not disjoin that means a single element is mapped to two values
-        Range::debug_assert_ranges_disjoint(&src_ranges);
-        Range::debug_assert_ranges_disjoint(&dst_ranges);
+        Range::debug_assert_disjoint(&src_ranges);
+        Range::debug_assert_disjoint(&dst_ranges);
         debug_assert!(src_ranges.len() == dst_ranges.len());
         debug_assert!(src_ranges
             .iter()
@@ -89,11 +59,124 @@ impl<'a> Map<'a> {
                 return dst_range.start + (val - src_range.start);
             }
         }
-        return val;
+        val
+    }
+
+    /// Every value split into (src, dst) pieces, sorted by src. Values no range covers are
+    /// identity pieces, so the pieces cover everything from 0 up
+    fn pieces(&self) -> Vec<(Range, Range)> {
+        let mut mapped: Vec<_> = self
+            .src_ranges
+            .iter()
+            .copied()
+            .zip(self.dst_ranges.iter().copied())
+            .collect();
+        mapped.sort_by_key(|(src_range, _)| src_range.start);
+
+        let mut pieces = Vec::new();
+        let mut covered = 0;
+        for (src_range, dst_range) in mapped {
+            if covered < src_range.start {
+                let gap = Range::new(covered, src_range.start);
+                pieces.push((gap, gap));
+            }
+            pieces.push((src_range, dst_range));
+            covered = src_range.end;
+        }
+        let rest = Range::new(covered, u64::MAX);
+        pieces.push((rest, rest));
+        pieces.retain(|(src_range, _)| !src_range.is_empty());
+        pieces
+    }
+
+    /// The single map that does self then next, so next.src_type has to be self.dst_type
+    fn compose(&self, next: &Map<'a>) -> Map<'a> {
+        debug_assert_eq!(self.dst_type, next.src_type);
+        let next_pieces = next.pieces();
+
+        // split each of self's pieces where its image crosses one of next's pieces
+        let mut pieces: Vec<(Range, Range)> = Vec::new();
+        for (src_range, mid_range) in self.pieces() {
+            for (next_src_range, next_dst_range) in &next_pieces {
+                let overlap = mid_range.intersection(next_src_range);
+                if overlap.is_empty() {
+                    continue;
+                }
+                let src_start = src_range.start + (overlap.start - mid_range.start);
+                let dst_start = next_dst_range.start + (overlap.start - next_src_range.start);
+                let src = Range::new(src_start, src_start + overlap.len());
+                let dst = Range::new(dst_start, dst_start + overlap.len());
+                match pieces.last_mut() {
+                    // neighbours that shift by the same amount are one piece
+                    Some((last_src, last_dst))
+                        if last_src.end == src.start && last_dst.end == dst.start =>
+                    {
+                        last_src.end = src.end;
+                        last_dst.end = dst.end;
+                    }
+                    _ => pieces.push((src, dst)),
+                }
+            }
+        }
+        pieces.sort_by_key(|(src_range, _)| src_range.start);
+
+        // Not Map::new, because two pieces can land on the same values when a map sends a range
+        // onto values it leaves alone elsewhere
+        let (src_ranges, dst_ranges): (Vec<_>, Vec<_>) = pieces
+            .into_iter()
+            .filter(|(src_range, dst_range)| src_range != dst_range)
+            .unzip();
+        Range::debug_assert_disjoint(&src_ranges);
+        Map {
+            src_type: self.src_type,
+            dst_type: next.dst_type,
+            src_ranges,
+            dst_ranges,
+        }
+    }
+
+    /// The src ranges that map into range. More than one src range can land on the same values, so
+    /// this works even when there's no inverse
+    fn preimage(&self, range: &Range) -> Vec<Range> {
+        let mut preimage: Vec<Range> = self
+            .pieces()
+            .into_iter()
+            .filter_map(|(src_range, dst_range)| {
+                let overlap = dst_range.intersection(range);
+                let start = src_range.start + (overlap.start - dst_range.start);
+                (!overlap.is_empty()).then(|| Range::new(start, start + overlap.len()))
+            })
+            .collect();
+        preimage.sort();
+        preimage
+    }
+
+    /// The map that undoes self, from dst_type back to src_type. None if two values map to the
+    /// same one, since then there's no single value to go back to
+    fn inverse(&self) -> Option<Map<'a>> {
+        let mut dst_ranges: Vec<_> = self
+            .pieces()
+            .into_iter()
+            .map(|(_, dst_range)| dst_range)
+            .collect();
+        dst_ranges.sort();
+        if dst_ranges
+            .windows(2)
+            .any(|window| window[0].overlaps(&window[1]))
+        {
+            return None;
+        }
+        Some(Map::new(
+            self.dst_type,
+            self.src_type,
+            self.dst_ranges.clone(),
+            self.src_ranges.clone(),
+        ))
     }
 }
 
-type AllMaps<'a> = HashMap<&'a str, Map<'a>>;
+/// Every map, grouped by the category it maps from
+type AllMaps<'a> = HashMap<&'a str, Vec<Map<'a>>>;
 
 /// Parse input. Input looks like the following
 ///
@@ -107,7 +190,7 @@ type AllMaps<'a> = HashMap<&'a str, Map<'a>>;
 ///     0 15 37
 ///     37 52 2
 ///     39 0 15
-fn parse<'a>(input: &'a str) -> IResult<&str, (Vec<u64>, AllMaps)> {
+fn parse<'a>(input: &'a str) -> IResult<&'a str, (Vec<u64>, AllMaps<'a>)> {
     // seeds: 79 14 55 13
     let (input, (_, _, _, seeds)) = tuple((
         tag("seeds"),
@@ -147,48 +230,62 @@ fn parse<'a>(input: &'a str) -> IResult<&str, (Vec<u64>, AllMaps)> {
         Ok((input, map))
     })(input)?;
 
-    let maps: HashMap<&'a str, Map> = maps.into_iter().map(|map| (map.src_type, map)).collect();
-    Ok((input, (seeds, maps)))
+    let mut all_maps = AllMaps::new();
+    for map in maps {
+        all_maps.entry(map.src_type).or_default().push(map);
+    }
+    Ok((input, (seeds, all_maps)))
 }
 
+/// Transform a seed one map at a time. compose_between does the same in one lookup
+#[cfg(test)]
 fn transform(mut seed: u64, maps: &AllMaps) -> Result<u64, String> {
-    let mut map = maps
-        .get("seed")
-        .ok_or("Could not find seed map".to_owned())?;
-    loop {
+    for map in categories::route(maps, "seed", "location").map_err(|err| err.to_string())? {
         seed = map.transform(seed);
-        if map.dst_type == "location" {
-            break;
-        }
-        map = maps
-            .get(map.dst_type)
-            .ok_or(format!("Could not find {} map", map.dst_type))?
     }
     Ok(seed)
 }
 
-fn transform_range(map: &Map, input_range: &Range) -> Vec<Range> {
-    let combined_ranges: Vec<_> = map
-        .src_ranges
-        .clone()
+/// The lowest location any seed in seed_ranges maps to, found by walking back from the lowest
+/// locations through inverse (a location to seed map) until one lands in a seed range
+fn lowest_location(inverse: &Map, seed_ranges: &[Range]) -> Option<u64> {
+    inverse
+        .pieces()
         .into_iter()
-        .zip(map.dst_ranges.clone())
+        .find_map(|(location_range, seed_range)| {
+            let seed = seed_ranges
+                .iter()
+                .map(|range| seed_range.intersection(range))
+                .filter(|overlap| !overlap.is_empty())
+                .map(|overlap| overlap.start)
+                .min()?;
+            Some(location_range.start + (seed - seed_range.start))
+        })
+}
+
+/// The pieces input_range is split into by map: the mapped pieces, and the "identity ranges" between
+/// them. Nothing is merged, so pieces that overlap or touch stay separate
+fn split_range(map: &Map, input_range: &Range) -> Vec<Range> {
+    let mut combined_ranges: Vec<_> = map
+        .src_ranges
+        .iter()
+        .copied()
+        .zip(map.dst_ranges.iter().copied())
         .collect();
-    let mut combined_ranges = combined_ranges.clone();
     combined_ranges.sort_by_key(|(src_range, _)| src_range.start);
 
     let mut relevant_src_ranges = Vec::new();
     let mut relevant_dst_ranges = Vec::new();
     for (src_range, dst_range) in combined_ranges {
         // filter by relevancy
-        if src_range.overlaps(&input_range) {
+        if src_range.overlaps(input_range) {
             relevant_src_ranges.push(src_range);
             relevant_dst_ranges.push(dst_range);
         }
     }
 
-    if relevant_src_ranges.len() == 0 {
-        return vec![input_range.clone()];
+    if relevant_src_ranges.is_empty() {
+        return vec![*input_range];
     }
     // truncate first src_range and dst_range to be in line with input range
     // truncate last src_range and dst_range to be in line with input range
@@ -203,44 +300,230 @@ fn transform_range(map: &Map, input_range: &Range) -> Vec<Range> {
         .unwrap()
         .end
         .saturating_sub(input_range.end);
-    (*relevant_src_ranges.last_mut().unwrap()).end -= right_delta;
-    (*relevant_dst_ranges.last_mut().unwrap()).end -= right_delta;
-
-    let output_ranges: Vec<Range> = {
-        let mut output_ranges: Vec<Range> = Vec::new();
-        // output all the dst ranges and
-        for dst_range in relevant_dst_ranges {
-            output_ranges.push(dst_range);
+    relevant_src_ranges.last_mut().unwrap().end -= right_delta;
+    relevant_dst_ranges.last_mut().unwrap().end -= right_delta;
+
+    // output all the dst ranges, and all the "identity ranges" between src ranges
+    let mut output_ranges = relevant_dst_ranges;
+    output_ranges.push(Range::new(input_range.start, relevant_src_ranges[0].start));
+    for slice in relevant_src_ranges.windows(2) {
+        output_ranges.push(Range::new(slice[0].end, slice[1].start));
+    }
+    output_ranges.push(Range::new(
+        relevant_src_ranges.last().unwrap().end,
+        input_range.end,
+    ));
+    output_ranges.retain(|range| !range.is_empty());
+    output_ranges.sort();
+
+    output_ranges
+}
+
+/// Like split_range, but pieces that overlap or touch come out as one
+fn transform_range(map: &Map, input_range: &Range) -> RangeSet {
+    split_range(map, input_range).into_iter().collect()
+}
+
+fn part1() -> Result<u64, Box<dyn Error>> {
+    let content = fs::read_to_string("src/d05/input")?;
+    let (_, (seeds, maps)) = all_consuming(parse)(content.as_ref()).expect("Could not parse input");
+
+    let almanac = compose_between(&maps, "seed", "location").map_err(|err| err.to_string())?;
+    let min = seeds
+        .iter()
+        .map(|seed| seed.transform(&almanac))
+        .min()
+        .expect("0 transformed values");
+
+    Ok(min)
+}
+
+/// The lowest location, and a trace of how the seed that gets there gets there
+fn part2() -> Result<(u64, String), Box<dyn Error>> {
+    // Get ranges from input
+    // let Transform = Map
+    // let SeedRanges = [[Range]]
+    // define f: Transform -> Range -> [Range]
+    // let f1, f2, f3 = f(Transform1), f(Transform2), f(Transform3)
+    // resulting ranges =  (f1 * f2 * f3)(SeedRanges)
+    let content = fs::read_to_string("src/d05/input")?;
+    let (_, (seed_input, maps)) =
+        all_consuming(parse)(content.as_ref()).expect("Could not parse input");
+    assert!(seed_input.len() % 2 == 0);
+
+    // TODO: why can't you call chunks_exact directly on Vec?
+    let mut ranges: Vec<_> = seed_input
+        .as_slice()
+        .chunks_exact(2)
+        .map(|chunk| {
+            let start = chunk[0];
+            let length = chunk[1];
+            Range::new(start, start + length)
+        })
+        .collect();
+
+    // assert that ranges are disjoint
+    ranges.sort();
+    Range::debug_assert_disjoint(&ranges);
+
+    // map ranges through all the maps at once
+    let almanac = compose_between(&maps, "seed", "location").map_err(|err| err.to_string())?;
+    let location_ranges: RangeSet = ranges
+        .iter()
+        .flat_map(|range| transform_range(&almanac, range))
+        .collect();
+    let min_value = location_ranges.iter().next().unwrap().start;
+
+    // check by going backwards: no seed maps below min_value but one maps to it
+    let reached = |locations: Range| {
+        almanac
+            .preimage(&locations)
+            .iter()
+            .any(|seeds| ranges.iter().any(|range| range.overlaps(seeds)))
+    };
+    if reached(Range::new(0, min_value)) || !reached(Range::new(min_value, min_value + 1)) {
+        return Err(format!("{} isn't the lowest location of any seed", min_value).into());
+    }
+    if let Some(inverse) = almanac.inverse() {
+        let backwards = lowest_location(&inverse, &ranges);
+        if backwards != Some(min_value) {
+            return Err(format!(
+                "lowest location is {} going forwards but {:?} going backwards",
+                min_value, backwards
+            )
+            .into());
         }
+    }
+
+    // find the seed again, this time keeping track of where it goes
+    let traced = provenance::trace(&maps, &ranges).map_err(|err| err.to_string())?;
+    let lowest = provenance::lowest(&traced).ok_or("no seeds")?;
+    if lowest.range().start != min_value {
+        return Err(format!(
+            "lowest location is {} but the traced seed ends up at {}",
+            min_value,
+            lowest.range().start
+        )
+        .into());
+    }
 
-        // output all the "identity ranges" between src ranges
-        output_ranges.push(Range::new(input_range.start, relevant_src_ranges[0].start));
-        relevant_src_ranges.as_slice().windows(2).for_each(|slice| {
-            output_ranges.push(Range::new(slice[0].end, slice[1].start));
-        });
-        output_ranges.push(Range::new(
-            relevant_src_ranges.last().unwrap().end,
-            input_range.end,
-        ));
-
-        // filter out any empty ranges
-        output_ranges = output_ranges
+    Ok((min_value, lowest.to_string()))
+}
+
+/// Answer a --between query: send query, either a value like 79 or a range like 79..93, from
+/// category src to category dst and print where it ends up
+fn between(src: &str, dst: &str, query: &str) -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string("src/d05/input")?;
+    let (_, (_, maps)) = all_consuming(parse)(content.as_ref()).expect("Could not parse input");
+    let err = |_| {
+        format!(
+            "expected a value like 79 or a range like 79..93 but got {}",
+            query
+        )
+    };
+    match query.split_once("..") {
+        Some((start, end)) => {
+            let (start, end) = (start.parse().map_err(err)?, end.parse().map_err(err)?);
+            if start > end {
+                return Err(format!("range {} ends before it starts", query).into());
+            }
+            let ranges = transform_between(&maps, src, dst, Range::new(start, end))
+                .map_err(|err| err.to_string())?;
+            for range in &ranges {
+                println!("{}..{}", range.start, range.end);
+            }
+        }
+        None => {
+            let value: u64 = query.parse().map_err(err)?;
+            let value = transform_between(&maps, src, dst, value).map_err(|err| err.to_string())?;
+            println!("{}", value);
+        }
+    }
+    Ok(())
+}
+
+/// The number of ranges after each map of route, starting with seed_ranges. Every step splits each
+/// range with split_range and collects the pieces into C, so a Vec keeps every fragment while a
+/// RangeSet merges whatever overlaps or touches
+fn range_counts<C>(route: &[&Map], seed_ranges: &[Range]) -> Vec<usize>
+where
+    C: FromIterator<Range>,
+    for<'c> &'c C: IntoIterator<Item = &'c Range>,
+{
+    let mut ranges: C = seed_ranges.iter().copied().collect();
+    let mut counts = vec![(&ranges).into_iter().count()];
+    for map in route {
+        ranges = (&ranges)
             .into_iter()
-            .filter(|r| r.start != r.end)
+            .flat_map(|range| split_range(map, range))
             .collect();
+        counts.push((&ranges).into_iter().count());
+    }
+    counts
+}
 
-        // sort
-        output_ranges.sort();
+/// How fragmented the seed ranges get on the way to location, with and without merging the ranges
+/// after each map
+fn bench() -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string("src/d05/input")?;
+    let (_, (seed_input, maps)) =
+        all_consuming(parse)(content.as_ref()).expect("Could not parse input");
+    let seed_ranges: Vec<_> = seed_input
+        .chunks_exact(2)
+        .map(|chunk| Range::new(chunk[0], chunk[0] + chunk[1]))
+        .collect();
+    let route = categories::route(&maps, "seed", "location").map_err(|err| err.to_string())?;
 
-        output_ranges
-    };
+    let start = Instant::now();
+    let unmerged_counts = range_counts::<Vec<Range>>(&route, &seed_ranges);
+    let unmerged_elapsed = start.elapsed();
 
-    output_ranges
+    let start = Instant::now();
+    let merged_counts = range_counts::<RangeSet>(&route, &seed_ranges);
+    let merged_elapsed = start.elapsed();
+
+    println!("{:<12} {:>10} {:>10}", "category", "unmerged", "merged");
+    let categories = std::iter::once("seed").chain(route.iter().map(|map| map.dst_type));
+    for (category, (unmerged, merged)) in categories.zip(unmerged_counts.iter().zip(&merged_counts))
+    {
+        println!("{:<12} {:>10} {:>10}", category, unmerged, merged);
+    }
+    println!(
+        "unmerged in {:.3}ms, merged in {:.3}ms",
+        unmerged_elapsed.as_secs_f64() * 1000.0,
+        merged_elapsed.as_secs_f64() * 1000.0
+    );
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // --bench: compare how many ranges part 2 has at each step with and without merging them
+    if env::args().any(|arg| arg == "--bench") {
+        return bench();
+    }
+    // --between <src> <dst> <value|start..end>: where a value or range of src ends up in dst
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--between") {
+        return match &args[pos + 1..] {
+            [src, dst, query, ..] => between(src, dst, query),
+            _ => {
+                Err("--between needs a source category, a destination category and a value".into())
+            }
+        };
+    }
+
+    println!("Part 1 answer: {}", part1().unwrap());
+    let (answer, trace) = part2().unwrap();
+    println!("Part 2 answer: {}", answer);
+    print!("{}", trace);
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use indoc::indoc;
+    use itertools::Itertools;
 
     #[test]
     fn test_transform_non_overlapping_ranges() {
@@ -260,6 +543,11 @@ mod test {
         //          55 - 65
         //          80 - 90
         //          10 - 20
+        //
+        //   which merge into
+        //          0 - 50
+        //          55 - 65
+        //          70 - 100
 
         let input_range = Range { start: 0, end: 100 };
         let map = Map::new(
@@ -269,21 +557,10 @@ mod test {
             vec![Range::new(55, 65), Range::new(80, 90), Range::new(10, 20)],
         );
 
-        let output_ranges = {
-            let mut output_ranges = transform_range(&map, &input_range);
-            output_ranges.sort();
-            output_ranges
-        };
+        let output_ranges: Vec<_> = transform_range(&map, &input_range).into_iter().collect();
 
         let expected_output_ranges = {
-            let mut v = vec![
-                Range::new(0, 10),
-                Range::new(20, 50),
-                Range::new(70, 100),
-                Range::new(55, 65),
-                Range::new(80, 90),
-                Range::new(10, 20),
-            ];
+            let mut v = vec![Range::new(0, 50), Range::new(55, 65), Range::new(70, 100)];
             v.sort();
             v
         };
@@ -291,6 +568,19 @@ mod test {
         assert_eq!(expected_output_ranges, output_ranges);
     }
 
+    #[test]
+    fn test_range_counts() {
+        let (_, (_, maps)) = all_consuming(parse)(EXAMPLE).unwrap();
+        let route = categories::route(&maps, "seed", "location").unwrap();
+        let seed_ranges = [Range::new(55, 68), Range::new(79, 93)];
+        let unmerged = range_counts::<Vec<Range>>(&route, &seed_ranges);
+        let merged = range_counts::<RangeSet>(&route, &seed_ranges);
+        assert_eq!(unmerged, vec![2, 2, 2, 3, 3, 4, 4, 7]);
+        // humidity-to-location splits 46 - 57 and 90 - 99 into 5 pieces that all land next to
+        // another one (46 - 56, 56 - 60, 60 - 61 and 94 - 97, 97 - 99), so merging joins them up
+        assert_eq!(merged, vec![2, 2, 2, 3, 3, 4, 4, 4]);
+    }
+
     #[test]
     fn test_transform_overlapping_ranges() {
         // input range 50 - 100
@@ -320,11 +610,7 @@ mod test {
             vec![Range::new(200, 210), Range::new(0, 5), Range::new(40, 50)],
         );
 
-        let output_ranges = {
-            let mut output_ranges = transform_range(&map, &input_range);
-            output_ranges.sort();
-            output_ranges
-        };
+        let output_ranges: Vec<_> = transform_range(&map, &input_range).into_iter().collect();
 
         let expected_output_ranges = {
             let mut v = vec![
@@ -340,74 +626,194 @@ mod test {
         assert_eq!(expected_output_ranges, output_ranges);
     }
 
+    pub const EXAMPLE: &str = indoc! {"
+        seeds: 79 14 55 13
+
+        seed-to-soil map:
+        50 98 2
+        52 50 48
+
+        soil-to-fertilizer map:
+        0 15 37
+        37 52 2
+        39 0 15
+
+        fertilizer-to-water map:
+        49 53 8
+        0 11 42
+        42 0 7
+        57 7 4
+
+        water-to-light map:
+        88 18 7
+        18 25 70
+
+        light-to-temperature map:
+        45 77 23
+        81 45 19
+        68 64 13
+
+        temperature-to-humidity map:
+        0 69 1
+        1 0 69
+
+        humidity-to-location map:
+        60 56 37
+        56 93 4
+    "};
+
     #[test]
-    fn test_answers() {
-        assert_eq!(part1().unwrap(), 322500873);
-        assert_eq!(part2().unwrap(), 108956227);
-    }
-}
+    fn test_compose() {
+        //   foo -> bar
+        //     10 - 20 : 30 - 40
+        //   bar -> baz
+        //     35 - 45 : 0 - 10
+        //
+        //   foo -> baz
+        //     10 - 15 : 30 - 35
+        //     15 - 20 : 0 - 5
+        //     35 - 45 : 0 - 10
+        let first = Map::new(
+            "foo",
+            "bar",
+            vec![Range::new(10, 20)],
+            vec![Range::new(30, 40)],
+        );
+        let second = Map::new(
+            "bar",
+            "baz",
+            vec![Range::new(35, 45)],
+            vec![Range::new(0, 10)],
+        );
+        let composed = first.compose(&second);
+        assert_eq!(
+            composed,
+            Map {
+                src_type: "foo",
+                dst_type: "baz",
+                src_ranges: vec![Range::new(10, 15), Range::new(15, 20), Range::new(35, 45)],
+                dst_ranges: vec![Range::new(30, 35), Range::new(0, 5), Range::new(0, 10)],
+            }
+        );
+        for val in 0..100 {
+            assert_eq!(
+                composed.transform(val),
+                second.transform(first.transform(val))
+            );
+        }
 
-fn part1() -> Result<u64, Box<dyn Error>> {
-    let content = fs::read_to_string("src/d05/input")?;
-    let (_, (seeds, maps)) = all_consuming(parse)(content.as_ref()).expect("Could not parse input");
+        // 0 - 10 goes there and back so it's dropped, but 50 - 60 is left alone by the first map
+        // and so still moves
+        let there = Map::new(
+            "foo",
+            "bar",
+            vec![Range::new(0, 10)],
+            vec![Range::new(50, 60)],
+        );
+        let back = Map::new(
+            "bar",
+            "baz",
+            vec![Range::new(50, 60)],
+            vec![Range::new(0, 10)],
+        );
+        let composed = there.compose(&back);
+        assert_eq!(composed.src_ranges, vec![Range::new(50, 60)]);
+        assert_eq!(composed.dst_ranges, vec![Range::new(0, 10)]);
+    }
 
-    let min = seeds
-        .iter()
-        .map(|seed| transform(*seed, &maps))
-        .process_results(|transformed| transformed.min().expect("0 transformed values"))?;
+    #[test]
+    fn test_compose_between() {
+        let (_, (seeds, maps)) = all_consuming(parse)(EXAMPLE).unwrap();
+        let almanac = compose_between(&maps, "seed", "location").unwrap();
+        assert_eq!((almanac.src_type, almanac.dst_type), ("seed", "location"));
+        for seed in 0..200 {
+            assert_eq!(almanac.transform(seed), transform(seed, &maps).unwrap());
+        }
+        let locations: Vec<_> = seeds.iter().map(|&seed| almanac.transform(seed)).collect();
+        assert_eq!(locations, vec![82, 43, 86, 35]);
+
+        // ranges through the composed map cover the same values as ranges through each map
+        for seed_range in [Range::new(79, 93), Range::new(55, 68), Range::new(0, 200)] {
+            let mut sequential = vec![seed_range];
+            for map in categories::route(&maps, "seed", "location").unwrap() {
+                sequential = sequential
+                    .iter()
+                    .flat_map(|range| transform_range(map, range))
+                    .collect();
+            }
+            let values = |ranges: Vec<Range>| -> Vec<u64> {
+                ranges
+                    .into_iter()
+                    .flat_map(|range| range.start..range.end)
+                    .sorted()
+                    .dedup()
+                    .collect()
+            };
+            assert_eq!(
+                values(transform_range(&almanac, &seed_range).into_iter().collect()),
+                values(sequential)
+            );
+        }
+    }
 
-    Ok(min)
-}
+    #[test]
+    fn test_inverse() {
+        let map = Map::new(
+            "foo",
+            "bar",
+            vec![Range::new(10, 20), Range::new(20, 30)],
+            vec![Range::new(20, 30), Range::new(10, 20)],
+        );
+        let inverse = map.inverse().unwrap();
+        assert_eq!((inverse.src_type, inverse.dst_type), ("bar", "foo"));
+        for val in 0..50 {
+            assert_eq!(inverse.transform(map.transform(val)), val);
+        }
+        assert_eq!(
+            map.preimage(&Range::new(15, 25)),
+            vec![Range::new(10, 15), Range::new(25, 30)]
+        );
 
-fn part2() -> Result<u64, Box<dyn Error>> {
-    // Get ranges from input
-    // let Transform = Map
-    // let SeedRanges = [[Range]]
-    // define f: Transform -> Range -> [Range]
-    // let f1, f2, f3 = f(Transform1), f(Transform2), f(Transform3)
-    // resulting ranges =  (f1 * f2 * f3)(SeedRanges)
-    let content = fs::read_to_string("src/d05/input")?;
-    let (_, (seed_input, maps)) =
-        all_consuming(parse)(content.as_ref()).expect("Could not parse input");
-    assert!(seed_input.len() % 2 == 0);
+        // 50 - 60 and 0 - 10 both land on 50 - 60, so there's no inverse
+        let map = Map::new(
+            "foo",
+            "bar",
+            vec![Range::new(0, 10)],
+            vec![Range::new(50, 60)],
+        );
+        assert_eq!(map.inverse(), None);
+        assert_eq!(
+            map.preimage(&Range::new(55, 65)),
+            vec![Range::new(5, 10), Range::new(55, 65)]
+        );
+    }
 
-    // TODO: why can't you call chunks_exact directly on Vec?
-    let mut ranges: Vec<_> = seed_input
-        .as_slice()
-        .chunks_exact(2)
-        .map(|chunk| {
-            let start = chunk[0];
-            let length = chunk[1];
-            Range::new(start, start + length)
-        })
-        .collect();
+    #[test]
+    fn test_inverse_chain() {
+        let (_, (seeds, maps)) = all_consuming(parse)(EXAMPLE).unwrap();
+        let almanac = compose_between(&maps, "seed", "location").unwrap();
+        let inverse = almanac.inverse().unwrap();
+        assert_eq!((inverse.src_type, inverse.dst_type), ("location", "seed"));
+        for seed in seeds {
+            assert_eq!(inverse.transform(almanac.transform(seed)), seed);
+        }
 
-    // assert that ranges are disjoint
-    ranges.sort();
-    Range::debug_assert_ranges_disjoint(&ranges);
-
-    // map ranges through all the maps
-    let mut map = maps
-        .get("seed")
-        .ok_or("Could not find seed map".to_owned())?;
-    loop {
-        ranges = ranges
-            .iter()
-            .flat_map(|range| transform_range(map, range))
-            .collect();
-        if map.dst_type == "location" {
-            break;
+        // which seeds map into locations 40 - 50
+        let seeds_for_locations = almanac.preimage(&Range::new(40, 50));
+        for seed in 0..200 {
+            let in_preimage = seeds_for_locations.iter().any(|range| range.contains(seed));
+            let location = almanac.transform(seed);
+            assert_eq!(in_preimage, (40..50).contains(&location), "seed {}", seed);
         }
-        map = maps
-            .get(map.dst_type)
-            .ok_or(format!("Could not find {} map", map.dst_type))?
+
+        let seed_ranges = [Range::new(55, 68), Range::new(79, 93)];
+        assert_eq!(lowest_location(&inverse, &seed_ranges), Some(46));
+        assert_eq!(lowest_location(&inverse, &[]), None);
     }
-    let min_value = ranges.iter().map(|range| range.start).min().unwrap();
 
-    Ok(min_value)
-}
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("Part 1 answer: {}", part1().unwrap());
-    println!("Part 2 answer: {}", part2().unwrap());
-    Ok(())
+    #[test]
+    fn test_answers() {
+        assert_eq!(part1().unwrap(), 322500873);
+        assert_eq!(part2().unwrap().0, 108956227);
+    }
 }
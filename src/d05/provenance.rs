@@ -0,0 +1,227 @@
+use super::*;
+
+use std::fmt;
+
+/// One map applied to a traced range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step<'m, 'a> {
+    pub map: &'m Map<'a>,
+    /// Index of the map entry that moved the range, or None if no entry covers it so it stays put
+    pub entry: Option<usize>,
+    /// The range afterwards, as map.dst_type values
+    pub range: Range,
+}
+
+/// A range of values along with the seeds they came from and every map entry on the way. Every
+/// map only shifts a range, so the seeds and every step's range are the same length and line up
+/// value for value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Traced<'m, 'a> {
+    pub seeds: Range,
+    pub steps: Vec<Step<'m, 'a>>,
+}
+
+impl<'m, 'a> Traced<'m, 'a> {
+    pub fn new(seeds: Range) -> Self {
+        Traced {
+            seeds,
+            steps: vec![],
+        }
+    }
+
+    /// The values the seeds have ended up as
+    pub fn range(&self) -> Range {
+        self.steps.last().map_or(self.seeds, |step| step.range)
+    }
+
+    /// The len values starting offset values in, at every step
+    pub fn slice(&self, offset: u64, len: u64) -> Self {
+        let slice = |range: Range| Range::new(range.start + offset, range.start + offset + len);
+        Traced {
+            seeds: slice(self.seeds),
+            steps: self
+                .steps
+                .iter()
+                .map(|step| Step {
+                    range: slice(step.range),
+                    ..step.clone()
+                })
+                .collect(),
+        }
+    }
+
+    /// Send the range through map, splitting it wherever a different entry (or none) applies
+    pub fn through(&self, map: &'m Map<'a>) -> Vec<Self> {
+        let range = self.range();
+        let mut pieces: Vec<(Range, Option<usize>, u64)> = map
+            .src_ranges
+            .iter()
+            .zip(&map.dst_ranges)
+            .enumerate()
+            .map(|(entry, (src_range, dst_range))| {
+                let overlap = src_range.intersection(&range);
+                let dst_start = dst_range.start + (overlap.start - src_range.start);
+                (overlap, Some(entry), dst_start)
+            })
+            .filter(|(overlap, _, _)| !overlap.is_empty())
+            .collect();
+        pieces.sort_by_key(|(overlap, _, _)| overlap.start);
+
+        // whatever no entry covers stays where it is
+        let mut gaps = vec![];
+        let mut covered = range.start;
+        for (overlap, _, _) in &pieces {
+            gaps.push(Range::new(covered, overlap.start));
+            covered = overlap.end;
+        }
+        gaps.push(Range::new(covered, range.end));
+        pieces.extend(
+            gaps.into_iter()
+                .filter(|gap| !gap.is_empty())
+                .map(|gap| (gap, None, gap.start)),
+        );
+        pieces.sort_by_key(|(overlap, _, _)| overlap.start);
+
+        pieces
+            .into_iter()
+            .map(|(overlap, entry, dst_start)| {
+                let mut traced = self.slice(overlap.start - range.start, overlap.len());
+                traced.steps.push(Step {
+                    map,
+                    entry,
+                    range: Range::new(dst_start, dst_start + overlap.len()),
+                });
+                traced
+            })
+            .collect()
+    }
+}
+
+/// Values are shown as a single number if there's only one of them
+fn show(range: Range) -> String {
+    if range.len() == 1 {
+        range.start.to_string()
+    } else {
+        format!("{}..{}", range.start, range.end)
+    }
+}
+
+/// One line per category, like
+///
+///     seed 82
+///     soil 84 (seed-to-soil line 2: 52 50 48)
+///     fertilizer 84 (not in soil-to-fertilizer)
+impl<'m, 'a> fmt::Display for Traced<'m, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seed_type = self.steps.first().map_or("seed", |step| step.map.src_type);
+        writeln!(f, "{} {}", seed_type, show(self.seeds))?;
+        for step in &self.steps {
+            let map = step.map;
+            write!(f, "{} {} ", map.dst_type, show(step.range))?;
+            match step.entry {
+                Some(entry) => writeln!(
+                    f,
+                    "({}-to-{} line {}: {} {} {})",
+                    map.src_type,
+                    map.dst_type,
+                    entry + 1,
+                    map.dst_ranges[entry].start,
+                    map.src_ranges[entry].start,
+                    map.src_ranges[entry].len()
+                )?,
+                None => writeln!(f, "(not in {}-to-{})", map.src_type, map.dst_type)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send seed_ranges from seed to location one map at a time, keeping track of how they got there
+pub fn trace<'m, 'a>(
+    maps: &'m AllMaps<'a>,
+    seed_ranges: &[Range],
+) -> Result<Vec<Traced<'m, 'a>>, RouteError<'a>> {
+    let mut traced: Vec<_> = seed_ranges.iter().copied().map(Traced::new).collect();
+    for map in categories::route(maps, "seed", "location")? {
+        traced = traced
+            .iter()
+            .flat_map(|traced| traced.through(map))
+            .collect();
+    }
+    Ok(traced)
+}
+
+/// The single seed that ends up at the lowest value
+pub fn lowest<'m, 'a>(traced: &[Traced<'m, 'a>]) -> Option<Traced<'m, 'a>> {
+    traced
+        .iter()
+        .min_by_key(|traced| traced.range().start)
+        .map(|traced| traced.slice(0, 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::EXAMPLE;
+    use indoc::indoc;
+
+    #[test]
+    fn test_through() {
+        let map = Map::new(
+            "foo",
+            "bar",
+            vec![Range::new(10, 20), Range::new(30, 40)],
+            vec![Range::new(110, 120), Range::new(0, 10)],
+        );
+        let traced = Traced::new(Range::new(5, 35)).through(&map);
+        let summary: Vec<_> = traced
+            .iter()
+            .map(|traced| (traced.seeds, traced.steps[0].entry, traced.range()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Range::new(5, 10), None, Range::new(5, 10)),
+                (Range::new(10, 20), Some(0), Range::new(110, 120)),
+                (Range::new(20, 30), None, Range::new(20, 30)),
+                (Range::new(30, 35), Some(1), Range::new(0, 5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let (_, (seeds, maps)) = all_consuming(parse)(EXAMPLE).unwrap();
+        let seed_ranges: Vec<_> = seeds
+            .chunks_exact(2)
+            .map(|chunk| Range::new(chunk[0], chunk[0] + chunk[1]))
+            .collect();
+        let traced = trace(&maps, &seed_ranges).unwrap();
+
+        // every piece lines up with transforming its seeds one at a time
+        for piece in &traced {
+            for offset in 0..piece.seeds.len() {
+                let one = piece.slice(offset, 1);
+                assert_eq!(
+                    one.range().start,
+                    transform(one.seeds.start, &maps).unwrap()
+                );
+            }
+        }
+
+        let lowest = lowest(&traced).unwrap();
+        assert_eq!(
+            lowest.to_string(),
+            indoc! {"
+                seed 82
+                soil 84 (seed-to-soil line 2: 52 50 48)
+                fertilizer 84 (not in soil-to-fertilizer)
+                water 84 (not in fertilizer-to-water)
+                light 77 (water-to-light line 2: 18 25 70)
+                temperature 45 (light-to-temperature line 1: 45 77 23)
+                humidity 46 (temperature-to-humidity line 2: 1 0 69)
+                location 46 (not in humidity-to-location)
+            "}
+        );
+    }
+}
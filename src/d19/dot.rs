@@ -0,0 +1,151 @@
+use super::*;
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+const HIGHLIGHT: &str = "color=blue, penwidth=2";
+
+/// Render workflows as a graphviz DOT graph. Every workflow is a node with an edge per rule
+/// (labelled with its condition) and a dashed edge for the default. A and R are their own terminal
+/// nodes. If part is given, the workflows and edges it passes through are highlighted
+pub fn to_dot(workflows: &[Workflow<'_>], part: Option<&Part>) -> String {
+    let path = part
+        .map(|part| part_path(workflows, part))
+        .unwrap_or_default();
+    let highlighted_edges: HashSet<(&str, usize)> = path.iter().copied().collect();
+    let mut highlighted_nodes: HashSet<&str> = path.iter().map(|&(name, _)| name).collect();
+    if let Some(&(name, edge)) = path.last() {
+        let workflow = workflows.iter().find(|wf| wf.name == name).unwrap();
+        highlighted_nodes.insert(ident_name(&edge_dst(workflow, edge)));
+    }
+
+    let mut out = String::new();
+    writeln!(out, "digraph workflows {{").unwrap();
+    writeln!(out, "    node [shape=box];").unwrap();
+    for (name, color) in [("A", "green"), ("R", "red")] {
+        let highlight = if highlighted_nodes.contains(name) {
+            ", penwidth=2"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "    \"{}\" [shape=doublecircle, color={}{}];",
+            name, color, highlight
+        )
+        .unwrap();
+    }
+
+    for workflow in workflows {
+        if highlighted_nodes.contains(workflow.name) {
+            writeln!(out, "    \"{}\" [{}];", workflow.name, HIGHLIGHT).unwrap();
+        } else {
+            writeln!(out, "    \"{}\";", workflow.name).unwrap();
+        }
+
+        for edge in 0..=workflow.rules.len() {
+            let mut attrs = match workflow.rules.get(edge) {
+                Some(rule) => vec![format!("label=\"{}\"", rule.cond)],
+                None => vec!["style=dashed".to_owned()],
+            };
+            if highlighted_edges.contains(&(workflow.name, edge)) {
+                attrs.push(HIGHLIGHT.to_owned());
+            }
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [{}];",
+                workflow.name,
+                ident_name(&edge_dst(workflow, edge)),
+                attrs.join(", ")
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Edge i of a workflow is rule i, or the default if i == rules.len()
+fn edge_dst<'a>(workflow: &Workflow<'a>, edge: usize) -> WorkflowIdentifier<'a> {
+    workflow
+        .rules
+        .get(edge)
+        .map(|rule| rule.dst)
+        .unwrap_or(workflow.default)
+}
+
+/// The (workflow name, edge) pairs part goes through starting from in. Stops early at workflows
+/// that don't exist, or if it comes back to a workflow it's already been through
+fn part_path<'a>(workflows: &[Workflow<'a>], part: &Part) -> Vec<(&'a str, usize)> {
+    let mut path = Vec::new();
+    let mut wf_ident: WorkflowIdentifier<'a> = Either::Right("in");
+    while let Either::Right(name) = wf_ident {
+        let Some(workflow) = workflows.iter().find(|wf| wf.name == name) else {
+            break;
+        };
+        if path.iter().any(|&(visited, _)| visited == name) {
+            break;
+        }
+        let edge = workflow
+            .rules
+            .iter()
+            .position(|rule| rule.cond.applies(part))
+            .unwrap_or(workflow.rules.len());
+        path.push((workflow.name, edge));
+        wf_ident = edge_dst(workflow, edge);
+    }
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_to_dot() {
+        let input = indoc! {"
+            in{s<1351:px,R}
+            px{a<2006:A,m>=2090:R,A}
+
+            {x=787,m=2655,a=1222,s=1000}
+        "};
+        let (workflows, _, parts) = parse(input).unwrap();
+
+        assert_eq!(
+            to_dot(&workflows, None),
+            indoc! {r#"
+                digraph workflows {
+                    node [shape=box];
+                    "A" [shape=doublecircle, color=green];
+                    "R" [shape=doublecircle, color=red];
+                    "in";
+                    "in" -> "px" [label="s<1351"];
+                    "in" -> "R" [style=dashed];
+                    "px";
+                    "px" -> "A" [label="a<2006"];
+                    "px" -> "R" [label="m>=2090"];
+                    "px" -> "A" [style=dashed];
+                }
+            "#}
+        );
+
+        assert_eq!(
+            to_dot(&workflows, Some(&parts[0])),
+            indoc! {r#"
+                digraph workflows {
+                    node [shape=box];
+                    "A" [shape=doublecircle, color=green, penwidth=2];
+                    "R" [shape=doublecircle, color=red];
+                    "in" [color=blue, penwidth=2];
+                    "in" -> "px" [label="s<1351", color=blue, penwidth=2];
+                    "in" -> "R" [style=dashed];
+                    "px" [color=blue, penwidth=2];
+                    "px" -> "A" [label="a<2006", color=blue, penwidth=2];
+                    "px" -> "R" [label="m>=2090"];
+                    "px" -> "A" [style=dashed];
+                }
+            "#}
+        );
+    }
+}
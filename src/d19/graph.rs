@@ -0,0 +1,665 @@
+use super::*;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The most attributes a part can have. part2 needs a separate copy of the range splitting code
+/// for each possible number of attributes
+pub const MAX_ATTRS: usize = 8;
+
+/// Like WorkflowIdentifier but with workflow names resolved to indices into WorkflowGraph
+pub type CompiledIdentifier = Either<AcceptReject, usize>;
+
+/// Like Condition but with the attribute name resolved to an index into WorkflowGraph::attrs (which
+/// is also the dimension of the NDRange the attribute corresponds to)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompiledCondition {
+    pub dimension: usize,
+    pub op: Op,
+    pub val: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledWorkflow<'a> {
+    pub name: &'a str,
+    pub rules: Vec<(CompiledCondition, CompiledIdentifier)>,
+    pub default: CompiledIdentifier,
+}
+
+/// The workflows with every destination resolved to an index. Guaranteed to have a start
+/// workflow, no dangling references and no cycles
+#[derive(Debug, Clone)]
+pub struct WorkflowGraph<'a> {
+    pub workflows: Vec<CompiledWorkflow<'a>>,
+    pub start: usize,
+    /// The attribute names of the parts, in order
+    pub attrs: Vec<&'a str>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompileError {
+    MissingStart,
+    DuplicateWorkflow(String),
+    DanglingReference { workflow: String, dst: String },
+    UnknownAttribute { workflow: String, attr: String },
+    TooManyAttributes(usize),
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::MissingStart => write!(f, "no workflow named in"),
+            CompileError::DuplicateWorkflow(name) => {
+                write!(f, "workflow {} is defined more than once", name)
+            }
+            CompileError::DanglingReference { workflow, dst } => write!(
+                f,
+                "workflow {} sends parts to {} which doesn't exist",
+                workflow, dst
+            ),
+            CompileError::UnknownAttribute { workflow, attr } => write!(
+                f,
+                "workflow {} tests attribute {} which parts don't have",
+                workflow, attr
+            ),
+            CompileError::TooManyAttributes(n) => write!(
+                f,
+                "parts have {} attributes, at most {} are supported",
+                n, MAX_ATTRS
+            ),
+            CompileError::Cycle(names) => write!(f, "cycle in workflows {}", names.join(" -> ")),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+impl CompiledCondition {
+    /// Whether a part with these values, in the order of WorkflowGraph::attrs, matches
+    pub fn applies(&self, vals: &[usize]) -> bool {
+        self.op.holds(vals[self.dimension], self.val)
+    }
+}
+
+impl<'a> CompiledWorkflow<'a> {
+    pub fn apply(&self, vals: &[usize]) -> CompiledIdentifier {
+        for (cond, dst) in &self.rules {
+            if cond.applies(vals) {
+                return *dst;
+            }
+        }
+        self.default
+    }
+
+    /// Indices of all the workflows this workflow can send parts to
+    pub fn successors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.rules
+            .iter()
+            .map(|(_, dst)| dst)
+            .chain(std::iter::once(&self.default))
+            .filter_map(|dst| dst.right())
+    }
+}
+
+/// One step of a part through the graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop<'a> {
+    pub workflow: &'a str,
+    /// The index of the rule that matched, or None if none did and the part went to the default
+    pub rule: Option<usize>,
+    /// The condition of that rule
+    pub cond: Option<Condition<'a>>,
+    pub dst: WorkflowIdentifier<'a>,
+}
+
+impl<'a> fmt::Display for Hop<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.rule, &self.cond) {
+            (Some(rule), Some(cond)) => write!(f, "{}: rule {} {}", self.workflow, rule, cond)?,
+            _ => write!(f, "{}: default", self.workflow)?,
+        }
+        write!(f, " -> {}", ident_name(&self.dst))
+    }
+}
+
+impl<'a> WorkflowGraph<'a> {
+    /// The part's values in the order of attrs, whatever order the part lists them in. Panics if
+    /// the part is missing one, which parse doesn't allow
+    pub fn values(&self, part: &Part) -> ArrayVec<usize, MAX_ATTRS> {
+        self.attrs
+            .iter()
+            .map(|attr| {
+                part.get(attr)
+                    .unwrap_or_else(|| panic!("part {} has no attribute {}", part, attr))
+            })
+            .collect()
+    }
+
+    pub fn send_part(&self, part: &Part) -> AcceptReject {
+        let vals = self.values(part);
+        let mut wf_ident = Either::Right(self.start);
+        loop {
+            match wf_ident {
+                Either::Right(idx) => wf_ident = self.workflows[idx].apply(&vals),
+                Either::Left(acc_rej) => return acc_rej,
+            }
+        }
+    }
+
+    /// Like send_part, but also returns every hop the part took to get there
+    pub fn explain(&self, part: &Part) -> (Vec<Hop<'a>>, AcceptReject) {
+        let vals = self.values(part);
+        let mut hops = Vec::new();
+        let mut idx = self.start;
+        loop {
+            let workflow = &self.workflows[idx];
+            let matched = workflow
+                .rules
+                .iter()
+                .position(|(cond, _)| cond.applies(&vals));
+            let (cond, dst) = match matched {
+                Some(rule) => {
+                    let (cond, dst) = workflow.rules[rule];
+                    (Some(self.decompile_cond(&cond)), dst)
+                }
+                None => (None, workflow.default),
+            };
+            hops.push(Hop {
+                workflow: workflow.name,
+                rule: matched,
+                cond,
+                dst: self.decompile_ident(dst),
+            });
+            match dst {
+                Either::Right(next) => idx = next,
+                Either::Left(acc_rej) => return (hops, acc_rej),
+            }
+        }
+    }
+
+    /// Turn a compiled condition back into the one it came from
+    pub fn decompile_cond(&self, cond: &CompiledCondition) -> Condition<'a> {
+        Condition {
+            attr: self.attrs[cond.dimension],
+            op: cond.op,
+            val: cond.val,
+        }
+    }
+
+    pub fn decompile_ident(&self, ident: CompiledIdentifier) -> WorkflowIdentifier<'a> {
+        ident.map_right(|idx| self.workflows[idx].name)
+    }
+}
+
+/// Resolve all the workflow names in workflows to indices, and the attribute names in their
+/// conditions to positions in attrs
+pub fn compile<'a>(
+    workflows: &[Workflow<'a>],
+    attrs: &[&'a str],
+) -> Result<WorkflowGraph<'a>, CompileError> {
+    if attrs.len() > MAX_ATTRS {
+        return Err(CompileError::TooManyAttributes(attrs.len()));
+    }
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+    for (idx, workflow) in workflows.iter().enumerate() {
+        if indices.insert(workflow.name, idx).is_some() {
+            return Err(CompileError::DuplicateWorkflow(workflow.name.to_owned()));
+        }
+    }
+
+    let resolve = |workflow: &Workflow<'a>, dst: &WorkflowIdentifier<'a>| match dst {
+        Either::Left(acc_rej) => Ok(Either::Left(*acc_rej)),
+        Either::Right(name) => indices
+            .get(name)
+            .map(|&idx| Either::Right(idx))
+            .ok_or_else(|| CompileError::DanglingReference {
+                workflow: workflow.name.to_owned(),
+                dst: name.to_string(),
+            }),
+    };
+
+    let resolve_cond = |workflow: &Workflow<'a>, cond: &Condition<'a>| {
+        let dimension = attrs
+            .iter()
+            .position(|&attr| attr == cond.attr)
+            .ok_or_else(|| CompileError::UnknownAttribute {
+                workflow: workflow.name.to_owned(),
+                attr: cond.attr.to_owned(),
+            })?;
+        Ok(CompiledCondition {
+            dimension,
+            op: cond.op,
+            val: cond.val,
+        })
+    };
+
+    let compiled = workflows
+        .iter()
+        .map(|workflow| {
+            let rules = workflow
+                .rules
+                .iter()
+                .map(|rule| {
+                    Ok((
+                        resolve_cond(workflow, &rule.cond)?,
+                        resolve(workflow, &rule.dst)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(CompiledWorkflow {
+                name: workflow.name,
+                rules,
+                default: resolve(workflow, &workflow.default)?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let successors: Vec<Vec<usize>> = compiled
+        .iter()
+        .map(|workflow| workflow.successors().collect())
+        .collect();
+    if let Some(cycle) = find_cycle(&successors) {
+        return Err(CompileError::Cycle(
+            cycle
+                .into_iter()
+                .map(|idx| compiled[idx].name.to_owned())
+                .collect(),
+        ));
+    }
+
+    let start = *indices.get("in").ok_or(CompileError::MissingStart)?;
+    Ok(WorkflowGraph {
+        workflows: compiled,
+        start,
+        attrs: attrs.to_vec(),
+    })
+}
+
+/// Depth first search for a cycle in a graph given as adjacency lists. Returns the nodes on the
+/// cycle, with the first one repeated at the end
+pub fn find_cycle(successors: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        Unvisited,
+        OnStack,
+        Done,
+    }
+
+    let mut marks = vec![Mark::Unvisited; successors.len()];
+    for root in 0..successors.len() {
+        if marks[root] != Mark::Unvisited {
+            continue;
+        }
+        // stack of (node, how many of its successors have been visited)
+        let mut stack = vec![(root, 0)];
+        marks[root] = Mark::OnStack;
+        while let Some((node, next_child)) = stack.last_mut() {
+            let node = *node;
+            let child = successors[node].get(*next_child).copied();
+            *next_child += 1;
+            match child {
+                None => {
+                    marks[node] = Mark::Done;
+                    stack.pop();
+                }
+                Some(child) => match marks[child] {
+                    Mark::Unvisited => {
+                        marks[child] = Mark::OnStack;
+                        stack.push((child, 0));
+                    }
+                    Mark::OnStack => {
+                        let cycle_start = stack.iter().position(|&(n, _)| n == child).unwrap();
+                        let mut cycle: Vec<_> =
+                            stack[cycle_start..].iter().map(|&(n, _)| n).collect();
+                        cycle.push(child);
+                        return Some(cycle);
+                    }
+                    Mark::Done => {}
+                },
+            }
+        }
+    }
+    None
+}
+
+/// Tarjan's algorithm for the strongly connected components of a graph given as adjacency lists.
+/// Each component's nodes are sorted, and the components are sorted by their first node
+pub fn strongly_connected_components(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; successors.len()];
+    let mut low = vec![UNVISITED; successors.len()];
+    let mut on_stack = vec![false; successors.len()];
+    // nodes visited but not yet put in a component
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut components = Vec::new();
+
+    for root in 0..successors.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // stack of (node, how many of its successors have been visited)
+        let mut dfs = vec![(root, 0)];
+        index[root] = next_index;
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, next_child)) = dfs.last_mut() {
+            let node = *node;
+            let child = successors[node].get(*next_child).copied();
+            *next_child += 1;
+            match child {
+                Some(child) if index[child] == UNVISITED => {
+                    index[child] = next_index;
+                    low[child] = next_index;
+                    next_index += 1;
+                    stack.push(child);
+                    on_stack[child] = true;
+                    dfs.push((child, 0));
+                }
+                Some(child) => {
+                    if on_stack[child] {
+                        low[node] = low[node].min(index[child]);
+                    }
+                }
+                None => {
+                    dfs.pop();
+                    if let Some(&(parent, _)) = dfs.last() {
+                        low[parent] = low[parent].min(low[node]);
+                    }
+                    if low[node] == index[node] {
+                        let mut component = Vec::new();
+                        loop {
+                            let member = stack.pop().unwrap();
+                            on_stack[member] = false;
+                            component.push(member);
+                            if member == node {
+                                break;
+                            }
+                        }
+                        component.sort();
+                        components.push(component);
+                    }
+                }
+            }
+        }
+    }
+    components.sort();
+    components
+}
+
+/// One cycle through each strongly connected component that has one (so a component of one node
+/// only counts if the node leads to itself). Each cycle is as from find_cycle
+pub fn find_cycles(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut component_of = vec![0; successors.len()];
+    let components = strongly_connected_components(successors);
+    for (idx, component) in components.iter().enumerate() {
+        for &node in component {
+            component_of[node] = idx;
+        }
+    }
+    (0..components.len())
+        .filter_map(|id| {
+            // the graph with only the edges inside this component
+            let inside: Vec<Vec<usize>> = successors
+                .iter()
+                .enumerate()
+                .map(|(node, succs)| {
+                    if component_of[node] != id {
+                        return vec![];
+                    }
+                    succs
+                        .iter()
+                        .copied()
+                        .filter(|&succ| component_of[succ] == id)
+                        .collect()
+                })
+                .collect();
+            find_cycle(&inside)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use nom::{character::complete::newline, multi::separated_list1};
+
+    const XMAS: [&str; 4] = ["x", "m", "a", "s"];
+
+    const EXAMPLE: &str = indoc! {"
+        px{a<2006:qkq,m>2090:A,rfg}
+        pv{a>1716:R,A}
+        lnx{m>1548:A,A}
+        rfg{s<537:gd,x>2440:R,A}
+        qs{s>3448:A,lnx}
+        qkq{x<1416:A,crn}
+        crn{x>2662:A,R}
+        in{s<1351:px,qqz}
+        qqz{s>2770:qs,m<1801:hdj,R}
+        gd{a>3333:R,R}
+        hdj{m>838:A,pv}
+
+        {x=787,m=2655,a=1222,s=2876}
+        {x=1679,m=44,a=2067,s=496}
+        {x=2036,m=264,a=79,s=2244}
+        {x=2461,m=1339,a=466,s=291}
+        {x=2127,m=1623,a=2188,s=1013}
+    "};
+
+    #[test]
+    fn test_example() {
+        let (workflows, attrs, parts) = parse(EXAMPLE).unwrap();
+        let graph = compile(&workflows, &attrs).unwrap();
+        let accepted: Vec<_> = parts.iter().map(|part| graph.send_part(part)).collect();
+        assert_eq!(
+            accepted,
+            vec![
+                AcceptReject::Accept,
+                AcceptReject::Reject,
+                AcceptReject::Accept,
+                AcceptReject::Reject,
+                AcceptReject::Accept,
+            ]
+        );
+        assert_eq!(part1(&graph, &parts), 19114);
+        assert_eq!(part2(&graph, &[Interval::new(1, 4001); 4]), 167409079868000);
+
+        // the lazy and parallel splitters should see exactly the same accepted pieces
+        let start = NDRange::new([Interval::new(1, 4001); 4]);
+        let accepted = |(range, acc_rej): (NDRange<usize, 4>, _)| {
+            (acc_rej == AcceptReject::Accept).then(|| range.volume())
+        };
+        let sequential: usize =
+            split_range_through_graph(start, Either::Right(graph.start), &graph)
+                .filter_map(accepted)
+                .sum();
+        let parallel: usize =
+            par_split_range_through_graph(start, Either::Right(graph.start), &graph)
+                .filter_map(accepted)
+                .sum();
+        assert_eq!(sequential, 167409079868000);
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_explain() {
+        let (workflows, attrs, parts) = parse(EXAMPLE).unwrap();
+        let graph = compile(&workflows, &attrs).unwrap();
+        let (hops, acc_rej) = graph.explain(&parts[1]);
+        assert_eq!(acc_rej, AcceptReject::Reject);
+        assert_eq!(
+            hops.iter().map(|hop| hop.to_string()).collect::<Vec<_>>(),
+            vec![
+                "in: rule 0 s<1351 -> px",
+                "px: default -> rfg",
+                "rfg: rule 0 s<537 -> gd",
+                "gd: default -> R",
+            ]
+        );
+        for part in &parts {
+            assert_eq!(graph.explain(part).1, graph.send_part(part));
+        }
+    }
+
+    #[test]
+    fn test_extended_operators() {
+        let input = indoc! {"
+            in{size>=10:big,size!=5:R,A}
+            big{weight==3:A,weight<=7:R,A}
+
+            {size=5,weight=1}
+            {size=10,weight=3}
+            {size=12,weight=7}
+            {size=4,weight=100}
+            {weight=9,size=20}
+        "};
+        let (workflows, attrs, parts) = parse(input).unwrap();
+        assert_eq!(attrs, vec!["size", "weight"]);
+        let graph = compile(&workflows, &attrs).unwrap();
+        let accepted: Vec<_> = parts.iter().map(|part| graph.send_part(part)).collect();
+        assert_eq!(
+            accepted,
+            vec![
+                AcceptReject::Accept,
+                AcceptReject::Accept,
+                AcceptReject::Reject,
+                AcceptReject::Reject,
+                AcceptReject::Accept,
+            ]
+        );
+        assert_eq!(part1(&graph, &parts), 48);
+        // size == 5 with any weight, or size >= 10 with weight == 3 or weight > 7
+        assert_eq!(part2(&graph, &[Interval::new(1, 21); 2]), 20 + 11 * 14);
+
+        // weight only up to 3
+        let bounds = [Interval::new(1, 21), Interval::new(1, 4)];
+        assert_eq!(part2(&graph, &bounds), 3 + 11);
+
+        assert_eq!(
+            accepted_boxes(&graph, &[Interval::new(1, 21); 2]),
+            vec![
+                vec![Interval::new(5, 6), Interval::new(1, 21)],
+                vec![Interval::new(10, 21), Interval::new(3, 4)],
+                vec![Interval::new(10, 21), Interval::new(8, 21)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        // 0 -> 1 -> 2 -> 0 and 1 -> 0 are one component, 3 only leads to itself, 4 -> 5 -> 4 is
+        // another, and 6 isn't on any cycle
+        let successors = vec![
+            vec![1],
+            vec![2, 0],
+            vec![0, 3],
+            vec![3],
+            vec![5, 6],
+            vec![4],
+            vec![],
+        ];
+        assert_eq!(
+            strongly_connected_components(&successors),
+            vec![vec![0, 1, 2], vec![3], vec![4, 5], vec![6]]
+        );
+        assert_eq!(
+            find_cycles(&successors),
+            vec![vec![0, 1, 2, 0], vec![3, 3], vec![4, 5, 4]]
+        );
+        assert_eq!(
+            find_cycles(&[vec![1], vec![2], vec![]]),
+            Vec::<Vec<usize>>::new()
+        );
+    }
+
+    #[test]
+    fn test_self_loop() {
+        // 1 leads to itself, 0 and 2 are only on the way in and out
+        let successors = vec![vec![1], vec![1, 2], vec![]];
+        assert_eq!(
+            strongly_connected_components(&successors),
+            vec![vec![0], vec![1], vec![2]]
+        );
+        assert_eq!(find_cycles(&successors), vec![vec![1, 1]]);
+    }
+
+    #[test]
+    fn test_disjoint_cycles() {
+        // 0 -> 1 -> 0 and 2 -> 3 -> 4 -> 2, with 1 -> 2 joining them one way only
+        let successors = vec![vec![1], vec![0, 2], vec![3], vec![4], vec![2]];
+        assert_eq!(
+            strongly_connected_components(&successors),
+            vec![vec![0, 1], vec![2, 3, 4]]
+        );
+        assert_eq!(
+            find_cycles(&successors),
+            vec![vec![0, 1, 0], vec![2, 3, 4, 2]]
+        );
+    }
+
+    #[test]
+    fn test_acyclic() {
+        // a diamond: 0 -> 1 -> 3 and 0 -> 2 -> 3
+        let successors = vec![vec![1, 2], vec![3], vec![3], vec![]];
+        assert_eq!(
+            strongly_connected_components(&successors),
+            vec![vec![0], vec![1], vec![2], vec![3]]
+        );
+        assert_eq!(find_cycles(&successors), Vec::<Vec<usize>>::new());
+        assert_eq!(find_cycles(&[]), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let parse_workflows = |input| {
+            let (_, workflows) = separated_list1(newline, parse::parse_workflow)(input).unwrap();
+            workflows
+        };
+
+        let workflows = parse_workflows("in{x<10:foo,A}");
+        assert_eq!(
+            compile(&workflows, &XMAS).unwrap_err(),
+            CompileError::DanglingReference {
+                workflow: "in".to_owned(),
+                dst: "foo".to_owned()
+            }
+        );
+
+        let workflows = parse_workflows("in{q<10:A,R}");
+        assert_eq!(
+            compile(&workflows, &XMAS).unwrap_err(),
+            CompileError::UnknownAttribute {
+                workflow: "in".to_owned(),
+                attr: "q".to_owned()
+            }
+        );
+
+        let workflows = parse_workflows("foo{x<10:A,R}");
+        assert_eq!(
+            compile(&workflows, &XMAS).unwrap_err(),
+            CompileError::MissingStart
+        );
+
+        let workflows = parse_workflows("in{x<10:A,R}\nin{x<10:A,R}");
+        assert_eq!(
+            compile(&workflows, &XMAS).unwrap_err(),
+            CompileError::DuplicateWorkflow("in".to_owned())
+        );
+
+        let workflows = parse_workflows("in{x<10:A,foo}\nfoo{m>5:bar,R}\nbar{a<3:in,A}");
+        assert_eq!(
+            compile(&workflows, &XMAS).unwrap_err(),
+            CompileError::Cycle(vec![
+                "in".to_owned(),
+                "foo".to_owned(),
+                "bar".to_owned(),
+                "in".to_owned()
+            ])
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use super::*;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use graph::{find_cycles, CompileError, MAX_ATTRS};
+
+/// Something suspicious about a set of workflows. None of these stop the workflows from running
+/// (compile catches the ones that do), but they usually point to a mistake in the rule set
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lint<'a> {
+    /// The rule at this index can never fire because earlier rules already handle every part it
+    /// matches
+    DeadRule { workflow: &'a str, rule: usize },
+    /// No part starting at in can ever reach this workflow
+    UnreachableWorkflow(&'a str),
+    /// The workflows on this cycle could send a part around forever. The first workflow is
+    /// repeated at the end. There's one of these for each group of workflows that can all reach
+    /// each other
+    Cycle(Vec<&'a str>),
+    /// Every rule that can fire, and the default if any part gets that far, sends parts to the
+    /// same place
+    ConstantOutcome {
+        workflow: &'a str,
+        dst: WorkflowIdentifier<'a>,
+    },
+}
+
+impl<'a> fmt::Display for Lint<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::DeadRule { workflow, rule } => write!(
+                f,
+                "rule {} of workflow {} can never fire, it's shadowed by earlier rules",
+                rule, workflow
+            ),
+            Lint::UnreachableWorkflow(name) => {
+                write!(f, "workflow {} is unreachable from in", name)
+            }
+            Lint::Cycle(names) => write!(f, "cycle in workflows {}", names.join(" -> ")),
+            Lint::ConstantOutcome { workflow, dst } => write!(
+                f,
+                "workflow {} always sends parts to {}",
+                workflow,
+                ident_name(dst)
+            ),
+        }
+    }
+}
+
+/// Run every check over workflows. Unlike compile this tolerates references to workflows that
+/// don't exist, they're just ignored. Fails if a workflow looks at more attributes than MAX_ATTRS
+pub fn lint<'a>(workflows: &[Workflow<'a>]) -> Result<Vec<Lint<'a>>, CompileError> {
+    let mut lints = Vec::new();
+
+    // dead rules and constant outcomes are checked one workflow at a time
+    for workflow in workflows {
+        let liveness = liveness(workflow)?;
+        for (idx, _) in liveness
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, live)| !**live)
+        {
+            lints.push(Lint::DeadRule {
+                workflow: workflow.name,
+                rule: idx,
+            });
+        }
+
+        let mut dsts = workflow
+            .rules
+            .iter()
+            .zip(liveness.rules.iter())
+            .filter(|(_, live)| **live)
+            .map(|(rule, _)| rule.dst)
+            .chain(liveness.default.then_some(workflow.default));
+        if let Some(first) = dsts.next() {
+            if dsts.all(|dst| dst == first) {
+                lints.push(Lint::ConstantOutcome {
+                    workflow: workflow.name,
+                    dst: first,
+                });
+            }
+        }
+    }
+
+    // the rest need the graph between workflows
+    let indices: HashMap<&str, usize> = workflows
+        .iter()
+        .enumerate()
+        .map(|(idx, workflow)| (workflow.name, idx))
+        .collect();
+    let successors: Vec<Vec<usize>> = workflows
+        .iter()
+        .map(|workflow| {
+            workflow
+                .rules
+                .iter()
+                .map(|rule| &rule.dst)
+                .chain(std::iter::once(&workflow.default))
+                .filter_map(|dst| dst.right())
+                .filter_map(|name| indices.get(name).copied())
+                .collect()
+        })
+        .collect();
+
+    let reachable: HashSet<usize> = {
+        let mut reachable = HashSet::new();
+        let mut queue: VecDeque<usize> = indices.get("in").copied().into_iter().collect();
+        while let Some(idx) = queue.pop_front() {
+            if reachable.insert(idx) {
+                queue.extend(successors[idx].iter());
+            }
+        }
+        reachable
+    };
+    lints.extend(
+        workflows
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !reachable.contains(idx))
+            .map(|(_, workflow)| Lint::UnreachableWorkflow(workflow.name)),
+    );
+
+    lints.extend(
+        find_cycles(&successors)
+            .into_iter()
+            .map(|cycle| Lint::Cycle(cycle.into_iter().map(|idx| workflows[idx].name).collect())),
+    );
+
+    Ok(lints)
+}
+
+/// Which parts of a workflow any part sent to it could ever use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    /// For each rule, whether any part could reach it and match it
+    pub rules: Vec<bool>,
+    /// Whether any part could get past every rule to the default
+    pub default: bool,
+}
+
+pub fn liveness(workflow: &Workflow<'_>) -> Result<Liveness, CompileError> {
+    // only the attributes the rules look at matter, so those are the dimensions
+    let mut attrs: Vec<&str> = Vec::new();
+    for rule in &workflow.rules {
+        if !attrs.contains(&rule.cond.attr) {
+            attrs.push(rule.cond.attr);
+        }
+    }
+    if attrs.len() > MAX_ATTRS {
+        return Err(CompileError::TooManyAttributes(attrs.len()));
+    }
+    Ok(with_dimensions!(attrs.len(), liveness_n(workflow, &attrs)))
+}
+
+fn liveness_n<const N: usize>(workflow: &Workflow<'_>, attrs: &[&str]) -> Liveness {
+    // Track the boxes of parts that make it past all the earlier rules. A rule is dead when it
+    // matches nothing in any of them, which can depend on earlier rules on other attributes
+    let mut remain = vec![NDRange::new([Interval::new(0, usize::MAX); N])];
+    let rules = workflow
+        .rules
+        .iter()
+        .map(|rule| {
+            let cond = CompiledCondition {
+                dimension: attrs
+                    .iter()
+                    .position(|&attr| attr == rule.cond.attr)
+                    .unwrap(),
+                op: rule.cond.op,
+                val: rule.cond.val,
+            };
+            let (this_ranges, rest): (Vec<_>, Vec<_>) =
+                remain.iter().map(|range| split(*range, &cond)).unzip();
+            remain = rest.concat();
+            this_ranges
+                .iter()
+                .any(|this_ranges| !this_ranges.is_empty())
+        })
+        .collect();
+    Liveness {
+        rules,
+        default: remain.iter().any(|range| !range.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nom::{character::complete::newline, multi::separated_list1};
+
+    fn parse_workflows(input: &str) -> Vec<Workflow<'_>> {
+        let (_, workflows) = separated_list1(newline, parse::parse_workflow)(input).unwrap();
+        workflows
+    }
+
+    #[test]
+    fn test_dead_rules() {
+        let workflows = parse_workflows("in{x<10:A,m>5:R,x<5:R,x<20:R,x>30:A,x<20:R,A}");
+        assert_eq!(
+            lint(&workflows).unwrap(),
+            vec![
+                Lint::DeadRule {
+                    workflow: "in",
+                    rule: 2
+                },
+                Lint::DeadRule {
+                    workflow: "in",
+                    rule: 5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_rules_extended_operators() {
+        // x!=3 and x==3 take every part between them, so nothing after them can fire
+        let workflows = parse_workflows("in{x!=3:A,x==3:R,x>=0:A,m<=5:R,m==5:A,m==6:R,R}");
+        assert_eq!(
+            lint(&workflows).unwrap(),
+            (2..6)
+                .map(|rule| Lint::DeadRule {
+                    workflow: "in",
+                    rule
+                })
+                .collect::<Vec<_>>()
+        );
+
+        // only x==3 gets past x!=3, so x>=4 is dead, and m==5 is dead after m<=5
+        let workflows = parse_workflows("in{x!=3:A,x>=4:R,m<=5:R,m==5:A,m==6:R,R}");
+        assert_eq!(
+            lint(&workflows).unwrap(),
+            vec![
+                Lint::DeadRule {
+                    workflow: "in",
+                    rule: 1
+                },
+                Lint::DeadRule {
+                    workflow: "in",
+                    rule: 3
+                },
+            ]
+        );
+
+        // m<5 is dead because the two rules on x before it already take every part
+        let workflows = parse_workflows("in{x<10:A,x>=10:R,m<5:A,R}");
+        assert_eq!(
+            lint(&workflows).unwrap(),
+            vec![Lint::DeadRule {
+                workflow: "in",
+                rule: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_too_many_attributes() {
+        let workflows =
+            parse_workflows("in{a<1:A,b<1:A,c<1:A,d<1:A,e<1:A,f<1:A,g<1:A,h<1:A,i<1:A,R}");
+        assert_eq!(
+            lint(&workflows).unwrap_err(),
+            CompileError::TooManyAttributes(9)
+        );
+        let workflows = parse_workflows("in{a<1:A,b<1:A,c<1:A,d<1:A,e<1:A,f<1:A,g<1:A,h<1:A,R}");
+        assert!(lint(&workflows).is_ok());
+    }
+
+    #[test]
+    fn test_unreachable_and_cycles() {
+        let workflows = parse_workflows(
+            "in{x<10:A,foo}\nfoo{m>5:bar,R}\nbar{a<3:foo,A}\nbaz{s<3:A,R}\nqux{x>5:in,R}",
+        );
+        assert_eq!(
+            lint(&workflows).unwrap(),
+            vec![
+                Lint::UnreachableWorkflow("baz"),
+                Lint::UnreachableWorkflow("qux"),
+                Lint::Cycle(vec!["foo", "bar", "foo"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_separate_cycles() {
+        // a and aa loop, and so do b and bb, but neither loop can get to the other. a also loops
+        // back to itself, which is part of the same group so isn't reported on its own
+        let workflows = parse_workflows(
+            "in{x<10:a,b}\na{m<3:aa,s<5:a,A}\naa{m<2:a,R}\nb{s<3:bb,R}\nbb{x<1:b,A}",
+        );
+        assert_eq!(
+            lint(&workflows)
+                .unwrap()
+                .into_iter()
+                .filter(|lint| matches!(lint, Lint::Cycle(_)))
+                .collect::<Vec<_>>(),
+            vec![
+                Lint::Cycle(vec!["a", "aa", "a"]),
+                Lint::Cycle(vec!["b", "bb", "b"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_outcome() {
+        // baz never gets to its default, every part is either below 10 or above 5
+        let workflows = parse_workflows(
+            "in{m>1548:A,A}\nfoo{x<5:A,x<3:R,A}\nbar{x<5:A,R}\nbaz{x<10:A,x>5:A,R}",
+        );
+        assert_eq!(
+            lint(&workflows)
+                .unwrap()
+                .into_iter()
+                .filter(|lint| matches!(lint, Lint::ConstantOutcome { .. }))
+                .collect::<Vec<_>>(),
+            vec![
+                Lint::ConstantOutcome {
+                    workflow: "in",
+                    dst: Either::Left(AcceptReject::Accept)
+                },
+                Lint::ConstantOutcome {
+                    workflow: "foo",
+                    dst: Either::Left(AcceptReject::Accept)
+                },
+                Lint::ConstantOutcome {
+                    workflow: "baz",
+                    dst: Either::Left(AcceptReject::Accept)
+                },
+            ]
+        );
+    }
+}
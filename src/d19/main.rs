@@ -1,108 +1,188 @@
-mod ndrange;
+/// Call the const generic function f::<N>(args) with N equal to the number of attributes n.
+/// split_range_through_graph and friends need to know the number of dimensions at compile time,
+/// so this picks the copy that matches. compile and lint::liveness check n is at most MAX_ATTRS
+/// (keep the list below in sync with it)
+macro_rules! with_dimensions {
+    ($n:expr, $f:ident $args:tt) => {
+        with_dimensions!(@arms $n, $f $args, 0 1 2 3 4 5 6 7 8)
+    };
+    (@arms $n:expr, $f:ident $args:tt, $($dims:literal)*) => {
+        match $n {
+            $($dims => $f::<$dims> $args,)*
+            n => unreachable!("parts with {} attributes", n),
+        }
+    };
+}
+
+mod dot;
+mod graph;
+mod lint;
+mod minimise;
 mod parse;
-use crate::ndrange::{NDRange, Range};
+use advent_of_code_2023::ndrange::{Interval, NDRange};
+use arrayvec::ArrayVec;
 use either::Either;
+use graph::{compile, CompiledCondition, CompiledIdentifier, WorkflowGraph};
 use parse::parse;
+use rayon::prelude::*;
+use serde::Serialize;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum AcceptReject {
     Accept,
     Reject,
 }
 
-#[derive(Debug, Clone)]
-enum PartAttr {
-    X,
-    M,
-    A,
-    S,
-}
-
-#[derive(Debug, Clone)]
-enum GTorLT {
-    GT,
+/// The comparison in a rule. E.g. the < in a<2006:qkq
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
     LT,
+    GT,
+    LE,
+    GE,
+    EQ,
+    NE,
 }
 
 type WorkflowIdentifier<'a> = Either<AcceptReject, &'a str>;
 
-#[derive(Debug, Clone)]
-struct WorkflowRule<'a> {
-    attr: PartAttr,
-    gtlt: GTorLT,
+/// The test part of a rule. E.g. a<2006 from a<2006:qkq
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition<'a> {
+    attr: &'a str,
+    op: Op,
     val: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WorkflowRule<'a> {
+    cond: Condition<'a>,
     dst: WorkflowIdentifier<'a>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Workflow<'a> {
     name: &'a str,
     rules: Vec<WorkflowRule<'a>>,
     default: WorkflowIdentifier<'a>,
 }
 
-impl<'a> WorkflowRule<'a> {
-    fn applies(&self, part: &Part) -> bool {
-        let val_of_attr = match self.attr {
-            PartAttr::X => part.x,
-            PartAttr::M => part.m,
-            PartAttr::A => part.a,
-            PartAttr::S => part.s,
-        };
-        let matches = match self.gtlt {
-            GTorLT::GT => val_of_attr > self.val,
-            GTorLT::LT => val_of_attr < self.val,
-        };
-        if matches {
-            return true;
+impl Op {
+    fn holds(&self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Op::LT => lhs < rhs,
+            Op::GT => lhs > rhs,
+            Op::LE => lhs <= rhs,
+            Op::GE => lhs >= rhs,
+            Op::EQ => lhs == rhs,
+            Op::NE => lhs != rhs,
         }
-        return false;
+    }
+
+    /// The values where `x op val` can change from true to false (or back) as x increases. Cutting
+    /// a range at these points leaves pieces where the op is either always or never true
+    fn cuts(&self, val: usize) -> ArrayVec<usize, 2> {
+        let next = val.saturating_add(1);
+        match self {
+            Op::LT | Op::GE => ArrayVec::from_iter([val]),
+            Op::LE | Op::GT => ArrayVec::from_iter([next]),
+            Op::EQ | Op::NE => ArrayVec::from([val, next]),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::LT => "<",
+            Op::GT => ">",
+            Op::LE => "<=",
+            Op::GE => ">=",
+            Op::EQ => "==",
+            Op::NE => "!=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl<'a> Condition<'a> {
+    /// Whether part matches this condition. Parts without the attribute never match
+    fn applies(&self, part: &Part) -> bool {
+        part.get(self.attr)
+            .is_some_and(|val| self.op.holds(val, self.val))
     }
 }
 
-impl<'a> Workflow<'a> {
-    fn apply(&self, part: &Part) -> WorkflowIdentifier<'a> {
+impl<'a> fmt::Display for Condition<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.attr, self.op, self.val)
+    }
+}
+
+impl<'a> fmt::Display for WorkflowRule<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.cond, ident_name(&self.dst))
+    }
+}
+
+impl<'a> fmt::Display for Workflow<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{{", self.name)?;
         for rule in &self.rules {
-            if rule.applies(part) {
-                return rule.dst.clone();
-            }
+            write!(f, "{},", rule)?;
         }
-        return self.default.clone();
+        write!(f, "{}}}", ident_name(&self.default))
+    }
+}
+
+/// The name a destination is written as in the input. E.g. A, R or px
+fn ident_name<'a>(ident: &WorkflowIdentifier<'a>) -> &'a str {
+    match ident {
+        Either::Left(AcceptReject::Accept) => "A",
+        Either::Left(AcceptReject::Reject) => "R",
+        Either::Right(name) => name,
     }
 }
 
-#[derive(Debug, Clone)]
-struct Part {
-    x: usize,
-    m: usize,
-    a: usize,
-    s: usize,
+/// A part is a list of (attribute name, value). E.g. {x=787,m=2655,a=1222,s=2876}. Every part in
+/// an input has the same attributes, but each keeps the order it was written in
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Part<'a> {
+    attrs: Vec<(&'a str, usize)>,
 }
 
-fn send_part(workflows: &Vec<Workflow>, part: &Part) -> AcceptReject {
-    let mut wf_ident = Either::Right("in");
-    loop {
-        match wf_ident {
-            Either::Right(name) => {
-                let wf = workflows
-                    .iter()
-                    .find(|Workflow { name: wf_name, .. }| *wf_name == name);
-                let wf = wf.unwrap(); // TODO: reutrn Err or something
-                wf_ident = wf.apply(part);
+impl<'a> Part<'a> {
+    /// The value of attr, or None if the part doesn't have it
+    fn get(&self, attr: &str) -> Option<usize> {
+        self.attrs
+            .iter()
+            .find(|(name, _)| *name == attr)
+            .map(|&(_, val)| val)
+    }
+}
+
+impl<'a> fmt::Display for Part<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (name, val)) in self.attrs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
             }
-            Either::Left(acc_rej) => return acc_rej,
+            write!(f, "{}={}", name, val)?;
         }
+        write!(f, "}}")
     }
 }
 
 /// Compute the answer for part 1
-fn part1(workflows: &Vec<Workflow<'_>>, parts: &Vec<Part>) -> usize {
+fn part1(graph: &WorkflowGraph<'_>, parts: &Vec<Part>) -> usize {
     // types
     //      Workflow { name, rules: Vec<WorkflowRule>, default: (A/R/Send) }
-    //      WorkflowRule { partattr, GTorLT, usize , (A/R/Send) }
-    //      Part { x, m, a, s }
+    //      WorkflowRule { Condition { attr, op, usize }, (A/R/Send) }
+    //      Part { attrs: [(name, usize)] }
     //
     // parse input
     //      parse_workflows -> Vec<Workflow>
@@ -120,134 +200,349 @@ fn part1(workflows: &Vec<Workflow<'_>>, parts: &Vec<Part>) -> usize {
     //  add all the numbers from the accepted parts
     let total: usize = parts
         .iter()
-        .filter(|part| send_part(&workflows, part) == AcceptReject::Accept)
-        .map(|part| {
-            let Part { x, m, a, s } = part.clone();
-            x + m + a + s
-        })
+        .filter(|part| graph.send_part(part) == AcceptReject::Accept)
+        .map(|part| part.attrs.iter().map(|(_, val)| val).sum::<usize>())
         .sum();
     total
 }
 
-fn part2(workflows: &Vec<Workflow<'_>>, lbound: usize, ubound: usize) -> usize {
-    let start = NDRange::new([
-        Range::new(lbound, ubound),
-        Range::new(lbound, ubound),
-        Range::new(lbound, ubound),
-        Range::new(lbound, ubound),
-    ]);
-    split_range_through_graph(start, Either::Right("in"), workflows)
-        .into_iter()
+/// The number of accepted parts with each attribute in its bounds. bounds has one entry per
+/// attribute, in the same order as graph.attrs
+fn part2(graph: &WorkflowGraph<'_>, bounds: &[Interval<usize>]) -> usize {
+    with_dimensions!(graph.attrs.len(), accepted_volume(graph, bounds))
+}
+
+fn start_range<const N: usize>(bounds: &[Interval<usize>]) -> NDRange<usize, N> {
+    assert_eq!(bounds.len(), N, "need bounds for every attribute");
+    NDRange::new(std::array::from_fn(|i| bounds[i]))
+}
+
+fn accepted_volume<const N: usize>(graph: &WorkflowGraph<'_>, bounds: &[Interval<usize>]) -> usize {
+    par_split_range_through_graph(start_range::<N>(bounds), Either::Right(graph.start), graph)
         .filter(|(_, acc_rej)| *acc_rej == AcceptReject::Accept)
-        .map(|(ndrange, _)| {
-            ndrange
-                .0
-                .iter()
-                .map(|range| range.end - range.start)
-                .product::<usize>()
-        })
+        .map(|(ndrange, _)| ndrange.volume())
         .sum()
 }
 
-// TODO: could you get this to return an iterator?
-/// Input: A range and a part of the graph to "DISPERSE" taht range through.
-/// Output: The set of all the tiny ranges
-fn split_range_through_graph<const N: usize>(
-    range: NDRange<N>,
-    wf_ident: WorkflowIdentifier,
-    workflows: &Vec<Workflow<'_>>,
-) -> Vec<(NDRange<N>, AcceptReject)> {
-    // Let's call this function DISPERSE. Psuedocode:
-    //
-    // DISPERSE(wf_ident, range) -> Vec<(Range, AcceptReject)> {
-    //      workflow <- get the workflow for this ident
-    //      result <- empty array
-    //      for rule in wf.rules
-    //          - pluck off the part of this NDRange that this rule applies to
-    //          - recursively send that through the graph (send it through the workflow for this
-    //          rule)
-    //          - set "remain" to be whatever's left after plucking off this part
-    //
-    //      - send "remain" through the default for this workflow
-    if matches!(wf_ident, Either::Left(_)) {
-        return vec![(range, wf_ident.unwrap_left())];
-    }
-    if range.is_empty() {
-        return Vec::new();
-    }
-    let wf_ident = wf_ident.unwrap_right();
-    let workflow = workflows
-        .iter()
-        .find(|Workflow { name: wf_name, .. }| *wf_name == wf_ident)
-        .expect(&format!("couldn't find workflow with name {}", wf_ident)); // TODO: no expect
-
-    let result = {
-        let mut remain = range;
-        let mut result: Vec<_> = Vec::new();
-
-        // for each rule, split off the piece of range handled by this rule and send it through
-        // it's part of the workflow graph
-        for rule in &workflow.rules {
-            let ((r1, r1_dst), r2) = split(remain, rule);
-            result.extend(split_range_through_graph(r1, r1_dst, workflows));
-            remain = r2;
-        }
+/// The accepted parts within bounds as a list of disjoint boxes, with boxes that share a face
+/// merged together. Each box is the interval of every attribute, in the same order as graph.attrs
+fn accepted_boxes(
+    graph: &WorkflowGraph<'_>,
+    bounds: &[Interval<usize>],
+) -> Vec<Vec<Interval<usize>>> {
+    with_dimensions!(graph.attrs.len(), accepted_boxes_n(graph, bounds))
+}
 
-        // whatever is remaining goes to default
-        result.extend(split_range_through_graph(
-            remain,
-            workflow.default.clone(),
-            workflows,
-        ));
+fn accepted_boxes_n<const N: usize>(
+    graph: &WorkflowGraph<'_>,
+    bounds: &[Interval<usize>],
+) -> Vec<Vec<Interval<usize>>> {
+    let accepted =
+        split_range_through_graph(start_range::<N>(bounds), Either::Right(graph.start), graph)
+            .filter(|(_, acc_rej)| *acc_rej == AcceptReject::Accept)
+            .map(|(ndrange, _)| ndrange);
+    NDRange::union(accepted)
+        .into_iter()
+        .map(|ndrange| ndrange.0.to_vec())
+        .collect()
+}
+
+/// What --accepted-json prints. Each box is a list of [start, end) for each attribute, in the same
+/// order as attrs. A part is accepted if it's in any of the boxes
+#[derive(Debug, Serialize)]
+struct AcceptedRegion<'a> {
+    attrs: &'a [&'a str],
+    boxes: Vec<Vec<[usize; 2]>>,
+}
 
-        // TODO: this might not be necessary?
-        result = result
+impl<'a> AcceptedRegion<'a> {
+    fn new(graph: &'a WorkflowGraph<'a>, bounds: &[Interval<usize>]) -> Self {
+        let boxes = accepted_boxes(graph, bounds)
             .into_iter()
-            .filter(|(range, _)| !range.is_empty())
+            .map(|ranges| {
+                ranges
+                    .iter()
+                    .map(|range| [range.start, range.end])
+                    .collect()
+            })
             .collect();
-        result
-    };
+        AcceptedRegion {
+            attrs: &graph.attrs,
+            boxes,
+        }
+    }
+}
 
-    result
+/// Parse a --bound argument. E.g. x=1..4001
+fn parse_bound(arg: &str) -> Result<(&str, Interval<usize>), String> {
+    let err = || format!("expected a bound like x=1..4001 but got {}", arg);
+    let (attr, range) = arg.split_once('=').ok_or_else(err)?;
+    let (start, end) = range.split_once("..").ok_or_else(err)?;
+    let (start, end) = (
+        start.parse::<usize>().map_err(|_| err())?,
+        end.parse::<usize>().map_err(|_| err())?,
+    );
+    if start > end {
+        return Err(err());
+    }
+    Ok((attr, Interval::new(start, end)))
 }
 
-/// Given a range and a rule, split the range into (this_range, remain) where
-///     this_range :: is part of the range handled by this rule (possibly empty)
-///     remain :: is the remaining part not handled by this rule (also possibly empty)
-///
-/// Since this_range is all handled by one rule, also give the destination for this_range
-fn split<'a, 'b, const N: usize>(
-    range: NDRange<N>,
-    rule: &'b WorkflowRule<'a>,
-) -> ((NDRange<N>, WorkflowIdentifier<'a>), NDRange<N>) {
-    let dimension: usize = match rule.attr {
-        PartAttr::X => 0,
-        PartAttr::M => 1,
-        PartAttr::A => 2,
-        PartAttr::S => 3,
+/// Input: A range and a part of the graph to "DISPERSE" that range through.
+/// Output: An iterator over all the tiny ranges, and whether each one is accepted or rejected
+fn split_range_through_graph<'g, 'a, const N: usize>(
+    range: NDRange<usize, N>,
+    wf_ident: CompiledIdentifier,
+    graph: &'g WorkflowGraph<'a>,
+) -> Disperse<'g, 'a, N> {
+    Disperse {
+        graph,
+        stack: vec![(range, wf_ident)],
+    }
+}
+
+/// Like split_range_through_graph, but the ranges coming out of the first workflow are sent
+/// through the rest of the graph in parallel
+fn par_split_range_through_graph<'g, 'a, const N: usize>(
+    range: NDRange<usize, N>,
+    wf_ident: CompiledIdentifier,
+    graph: &'g WorkflowGraph<'a>,
+) -> impl ParallelIterator<Item = (NDRange<usize, N>, AcceptReject)> + 'g {
+    let first_step = match wf_ident {
+        Either::Left(_) => vec![(range, wf_ident)],
+        Either::Right(idx) => disperse_through_workflow(range, idx, graph),
     };
+    first_step
+        .into_par_iter()
+        .flat_map_iter(move |(range, wf_ident)| split_range_through_graph(range, wf_ident, graph))
+}
+
+/// Iterator for split_range_through_graph. Keeps an explicit stack of the ranges that still need
+/// to be sent through the graph instead of recursing, so only one path through the graph is
+/// expanded at a time
+struct Disperse<'g, 'a, const N: usize> {
+    graph: &'g WorkflowGraph<'a>,
+    stack: Vec<(NDRange<usize, N>, CompiledIdentifier)>,
+}
+
+impl<'g, 'a, const N: usize> Iterator for Disperse<'g, 'a, N> {
+    type Item = (NDRange<usize, N>, AcceptReject);
 
+    fn next(&mut self) -> Option<Self::Item> {
+        // Psuedocode:
+        //
+        // DISPERSE(wf_ident, range) {
+        //      workflow <- get the workflow for this ident
+        //      for rule in wf.rules
+        //          - pluck off the part of this NDRange that this rule applies to
+        //          - push it on the stack to be sent through the workflow for this rule
+        //          - set "remain" to be whatever's left after plucking off this part
+        //
+        //      - push "remain" on the stack to be sent through the default for this workflow
+        //  }
+        //
+        //  pop ranges off the stack and DISPERSE them until one reaches A or R
+        while let Some((range, wf_ident)) = self.stack.pop() {
+            if range.is_empty() {
+                continue;
+            }
+            match wf_ident {
+                Either::Left(acc_rej) => return Some((range, acc_rej)),
+                Either::Right(idx) => {
+                    let pieces = disperse_through_workflow(range, idx, self.graph);
+                    // reversed so pieces come off the stack in rule order
+                    self.stack.extend(pieces.into_iter().rev());
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Split range up by the rules of the workflow at idx. Returns each piece along with where it
+/// goes next
+fn disperse_through_workflow<const N: usize>(
+    range: NDRange<usize, N>,
+    idx: usize,
+    graph: &WorkflowGraph<'_>,
+) -> Vec<(NDRange<usize, N>, CompiledIdentifier)> {
+    let workflow = &graph.workflows[idx];
+    let mut remain = vec![range];
+    let mut pieces = Vec::new();
+
+    // for each rule, split off the pieces of range handled by this rule
+    for (cond, dst) in &workflow.rules {
+        let mut next_remain = Vec::new();
+        for range in remain {
+            let (this_ranges, rest) = split(range, cond);
+            pieces.extend(this_ranges.into_iter().map(|this_range| (this_range, *dst)));
+            next_remain.extend(rest);
+        }
+        remain = next_remain;
+    }
+
+    // whatever is remaining goes to default
+    pieces.extend(remain.into_iter().map(|range| (range, workflow.default)));
+    pieces
+}
+
+/// Given a range and a condition, split the range into (this_ranges, remain) where
+///     this_ranges :: are the parts of the range matching the condition (possibly none)
+///     remain :: are the remaining parts not matching it (also possibly none)
+///
+/// == and != cut the range in two places, so either side can be more than one box
+fn split<const N: usize>(
+    range: NDRange<usize, N>,
+    cond: &CompiledCondition,
+) -> (Vec<NDRange<usize, N>>, Vec<NDRange<usize, N>>) {
     // Suppose this dimension's range is [0, 10)
-    //      if the rule is < 5 then this_range is the left part of
+    //      if the rule is < 5 then cut at 5 and this_range is the left part of
     //              **[0, 5)**, [5, 10)
-    //      if the rule is > 5 then this_range is the right part of
+    //      if the rule is > 5 then cut at 6 and this_range is the right part of
     //              [0, 6), **[6, 10)**
-    let (this_range, remain) = match rule.gtlt {
-        GTorLT::LT => range.split(dimension, rule.val),
-        GTorLT::GT => {
-            let (remain, this_range) = range.split(dimension, rule.val + 1);
-            (this_range, remain)
-        }
-    };
-
-    ((this_range, rule.dst.clone()), remain)
+    //      if the rule is == 5 then cut at 5 and 6 and this_range is the middle of
+    //              [0, 5), **[5, 6)**, [6, 10)
+    let CompiledCondition { dimension, op, val } = *cond;
+    let mut pieces = Vec::new();
+    let mut rest = range;
+    for cut in op.cuts(val) {
+        let (piece, remain) = rest.split(dimension, cut);
+        pieces.push(piece);
+        rest = remain;
+    }
+    pieces.push(rest);
+    pieces
+        .into_iter()
+        .filter(|piece| !piece.is_empty())
+        .partition(|piece| op.holds(piece.0[dimension].start, val))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
     let content = fs::read_to_string("src/d19/input")?;
-    let (workflows, parts) = parse(&content)?;
+    let (workflows, attrs, parts) = parse(&content)?;
+
+    // --dot [part index] prints the workflow graph (highlighting the path of that part) and exits
+    if let Some(pos) = args.iter().position(|arg| arg == "--dot") {
+        let part = match args.get(pos + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(arg) => {
+                let idx: usize = arg
+                    .parse()
+                    .map_err(|_| format!("expected a part index after --dot but got {}", arg))?;
+                let part = parts.get(idx).ok_or_else(|| {
+                    format!("no part {}, there are only {} parts", idx, parts.len())
+                })?;
+                Some(part)
+            }
+            None => None,
+        };
+        print!("{}", dot::to_dot(&workflows, part));
+        return Ok(());
+    }
+
+    for lint in lint::lint(&workflows)? {
+        eprintln!("warning: {}", lint);
+    }
+    let graph = compile(&workflows, &attrs)?;
+
+    // --bound x=1..100 overrides the [1, 4001) used for part 2 for one attribute. Can be repeated
+    let mut bounds = vec![Interval::new(1, 4001); attrs.len()];
+    for (pos, _) in args.iter().enumerate().filter(|(_, arg)| *arg == "--bound") {
+        let (attr, bound) = parse_bound(args.get(pos + 1).ok_or("--bound needs a value")?)?;
+        let dimension = attrs
+            .iter()
+            .position(|&other| other == attr)
+            .ok_or_else(|| format!("parts don't have attribute {}", attr))?;
+        bounds[dimension] = bound;
+    }
+
+    // --accepted-json prints the accepted region for part 2 as json and exits
+    if args.iter().any(|arg| arg == "--accepted-json") {
+        let region = AcceptedRegion::new(&graph, &bounds);
+        println!("{}", serde_json::to_string(&region)?);
+        return Ok(());
+    }
+
+    // --minimise prints an equivalent, smaller set of workflows and exits
+    if args.iter().any(|arg| arg == "--minimise") {
+        let minimised = minimise::minimise(&workflows, &attrs)?;
+        for workflow in &minimised {
+            println!("{}", workflow);
+        }
+        let minimised_graph = compile(&minimised, &attrs)?;
+        if !minimise::same_accepted_region(&graph, &minimised_graph, &bounds) {
+            return Err("minimised workflows accept different parts".into());
+        }
+        eprintln!(
+            "{} workflows with {} rules minimised to {} workflows with {} rules",
+            workflows.len(),
+            workflows.iter().map(|wf| wf.rules.len()).sum::<usize>(),
+            minimised.len(),
+            minimised.iter().map(|wf| wf.rules.len()).sum::<usize>(),
+        );
+        return Ok(());
+    }
 
-    println!("p1 {}", part1(&workflows, &parts));
-    println!("p2 {}", part2(&workflows, 1, 4001));
+    // --trace prints the path each part takes through the workflows
+    if args.iter().any(|arg| arg == "--trace") {
+        for (i, part) in parts.iter().enumerate() {
+            let (hops, acc_rej) = graph.explain(part);
+            println!("part {}: {:?}", i, acc_rej);
+            for hop in hops {
+                println!("    {}", hop);
+            }
+        }
+    }
+
+    println!("p1 {}", part1(&graph, &parts));
+    println!("p2 {}", part2(&graph, &bounds));
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nom::{character::complete::newline, multi::separated_list1};
+
+    const XMAS: [&str; 4] = ["x", "m", "a", "s"];
+
+    #[test]
+    fn test_parse_bound() {
+        assert_eq!(parse_bound("x=1..4001"), Ok(("x", Interval::new(1, 4001))));
+        assert_eq!(parse_bound("m=5..5"), Ok(("m", Interval::new(5, 5))));
+        for bad in ["x", "x=1", "x=1..", "=..4", "x=a..4", "x=-1..4", "x=9..4"] {
+            assert!(parse_bound(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_accepted_json() {
+        let input = "in{x<3:A,m>1:px,R}\npx{a<2:A,R}";
+        let (_, workflows) = separated_list1(newline, parse::parse_workflow)(input).unwrap();
+        let graph = compile(&workflows, &XMAS).unwrap();
+        let bounds = [Interval::new(1, 5); 4];
+
+        let region = AcceptedRegion::new(&graph, &bounds);
+        let volume: usize = region
+            .boxes
+            .iter()
+            .map(|ranges| {
+                ranges
+                    .iter()
+                    .map(|[start, end]| end - start)
+                    .product::<usize>()
+            })
+            .sum();
+        assert_eq!(volume, part2(&graph, &bounds));
+
+        let json: serde_json::Value = serde_json::to_value(&region).unwrap();
+        assert_eq!(json["attrs"], serde_json::json!(["x", "m", "a", "s"]));
+        for ranges in json["boxes"].as_array().unwrap() {
+            assert_eq!(ranges.as_array().unwrap().len(), 4);
+        }
+        assert!(json["boxes"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!([[1, 3], [1, 5], [1, 5], [1, 5]])));
+    }
+}
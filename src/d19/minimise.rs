@@ -0,0 +1,355 @@
+use super::*;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use graph::CompileError;
+
+/// Rewrite workflows into a smaller set that accepts exactly the same parts. Repeats these steps
+/// until nothing changes
+///     - drop rules that can never fire, and rules at the end that go to the same place as the
+///       default
+///     - merge consecutive rules on the same attribute with the same destination, when a single
+///       condition covers both
+///     - replace workflows where every path ends in the same outcome with A or R
+///     - replace workflows with no rules with their default
+///     - inline workflows that are only used as the default of one other workflow
+///     - drop workflows that can't be reached from in
+pub fn minimise<'a>(
+    workflows: &[Workflow<'a>],
+    attrs: &[&'a str],
+) -> Result<Vec<Workflow<'a>>, CompileError> {
+    let mut workflows = workflows.to_vec();
+    loop {
+        let before = workflows.clone();
+        for workflow in workflows.iter_mut() {
+            simplify_rules(workflow)?;
+        }
+        collapse_constant_workflows(&mut workflows, attrs)?;
+        inline_single_use_workflows(&mut workflows);
+        remove_unreachable_workflows(&mut workflows);
+        if workflows == before {
+            return Ok(workflows);
+        }
+    }
+}
+
+/// Whether the two graphs accept exactly the same parts within bounds (one per attribute)
+pub fn same_accepted_region(
+    graph1: &WorkflowGraph<'_>,
+    graph2: &WorkflowGraph<'_>,
+    bounds: &[Interval<usize>],
+) -> bool {
+    graph1.attrs == graph2.attrs
+        && with_dimensions!(
+            graph1.attrs.len(),
+            same_accepted_region_n(graph1, graph2, bounds)
+        )
+}
+
+fn same_accepted_region_n<const N: usize>(
+    graph1: &WorkflowGraph<'_>,
+    graph2: &WorkflowGraph<'_>,
+    bounds: &[Interval<usize>],
+) -> bool {
+    let accepted = |graph: &WorkflowGraph<'_>| -> Vec<NDRange<usize, N>> {
+        split_range_through_graph(start_range(bounds), Either::Right(graph.start), graph)
+            .filter(|(_, acc_rej)| *acc_rej == AcceptReject::Accept)
+            .map(|(range, _)| range)
+            .collect()
+    };
+    let (accepted1, accepted2) = (accepted(graph1), accepted(graph2));
+
+    // The boxes from split_range_through_graph are disjoint, so the regions are the same exactly
+    // when they both have the same volume as their overlap
+    let volume = |ranges: &[NDRange<usize, N>]| ranges.iter().map(NDRange::volume).sum::<usize>();
+    let overlap: usize = accepted1
+        .iter()
+        .flat_map(|r1| accepted2.iter().map(|r2| r1.intersect(r2).volume()))
+        .sum();
+    volume(&accepted1) == overlap && volume(&accepted2) == overlap
+}
+
+fn simplify_rules(workflow: &mut Workflow<'_>) -> Result<(), CompileError> {
+    let liveness = lint::liveness(workflow)?;
+    let mut live_rules: Vec<WorkflowRule<'_>> = workflow
+        .rules
+        .iter()
+        .zip(liveness.rules)
+        .filter(|(_, live)| *live)
+        .map(|(rule, _)| rule.clone())
+        .collect();
+    // nothing gets past the last live rule, so it may as well be the default
+    if !liveness.default {
+        if let Some(last) = live_rules.pop() {
+            workflow.default = last.dst;
+        }
+    }
+
+    let mut rules: Vec<WorkflowRule<'_>> = Vec::new();
+    for rule in &live_rules {
+        let merged = rules
+            .last()
+            .filter(|last| last.dst == rule.dst && last.cond.attr == rule.cond.attr)
+            .map(|last| merge_conditions(&last.cond, &rule.cond));
+        match merged {
+            // the two rules together match every part, so nothing after them matters
+            Some(Some(None)) => {
+                rules.pop();
+                workflow.default = rule.dst;
+                break;
+            }
+            Some(Some(Some(cond))) => rules.last_mut().unwrap().cond = cond,
+            Some(None) | None => rules.push(rule.clone()),
+        }
+    }
+    while rules
+        .last()
+        .is_some_and(|last| last.dst == workflow.default)
+    {
+        rules.pop();
+    }
+    workflow.rules = rules;
+    Ok(())
+}
+
+/// The single condition that matches whatever either cond1 or cond2 matches. None if there isn't
+/// one, Some(None) if the two together match everything
+fn merge_conditions<'a>(
+    cond1: &Condition<'a>,
+    cond2: &Condition<'a>,
+) -> Option<Option<Condition<'a>>> {
+    debug_assert!(cond1.attr == cond2.attr);
+    let matching = |cond: &Condition<'_>| {
+        let cond = CompiledCondition {
+            dimension: 0,
+            op: cond.op,
+            val: cond.val,
+        };
+        let (this_ranges, _) = split(NDRange::new([Interval::new(0, usize::MAX)]), &cond);
+        this_ranges
+    };
+    let union = NDRange::union(matching(cond1).into_iter().chain(matching(cond2)));
+    let intervals: Vec<Interval<usize>> = union.iter().map(|range| range.0[0]).collect();
+
+    let (op, val) = match intervals.as_slice() {
+        [Interval {
+            start: 0,
+            end: usize::MAX,
+        }] => return Some(None),
+        [Interval { start: 0, end }] => (Op::LT, *end),
+        [Interval {
+            start,
+            end: usize::MAX,
+        }] => (Op::GT, start - 1),
+        [Interval { start, end }] if *end == start + 1 => (Op::EQ, *start),
+        [Interval { start: 0, end }, Interval {
+            start,
+            end: usize::MAX,
+        }] if *start == end + 1 => (Op::NE, *end),
+        _ => return None,
+    };
+    Some(Some(Condition {
+        attr: cond1.attr,
+        op,
+        val,
+    }))
+}
+
+/// Point every reference to from at to instead
+fn redirect<'a>(workflows: &mut [Workflow<'a>], from: &str, to: WorkflowIdentifier<'a>) {
+    for workflow in workflows.iter_mut() {
+        let dsts = workflow
+            .rules
+            .iter_mut()
+            .map(|rule| &mut rule.dst)
+            .chain(std::iter::once(&mut workflow.default));
+        for dst in dsts {
+            if *dst == Either::Right(from) {
+                *dst = to;
+            }
+        }
+    }
+}
+
+fn collapse_constant_workflows<'a>(
+    workflows: &mut Vec<Workflow<'a>>,
+    attrs: &[&'a str],
+) -> Result<(), CompileError> {
+    let graph = compile(workflows, attrs)?;
+    let outcomes: Vec<Option<AcceptReject>> = (0..graph.workflows.len())
+        .map(|idx| with_dimensions!(attrs.len(), constant_outcome(&graph, idx)))
+        .collect();
+
+    let mut replacements: Vec<(&'a str, WorkflowIdentifier<'a>)> = Vec::new();
+    for (workflow, outcome) in workflows.iter_mut().zip(outcomes) {
+        let replacement = match outcome {
+            Some(acc_rej) => Either::Left(acc_rej),
+            None if workflow.rules.is_empty() => workflow.default,
+            None => continue,
+        };
+        if workflow.name == "in" {
+            workflow.rules.clear();
+            workflow.default = replacement;
+        } else {
+            replacements.push((workflow.name, replacement));
+        }
+    }
+
+    // replace one at a time, following earlier replacements, since a workflow with no rules might
+    // point at another workflow being replaced
+    let mut replaced: HashMap<&str, WorkflowIdentifier<'a>> = HashMap::new();
+    for (name, mut replacement) in replacements {
+        while let Some(next) = replacement.right().and_then(|to| replaced.get(to)) {
+            replacement = *next;
+        }
+        workflows.retain(|wf| wf.name != name);
+        redirect(workflows, name, replacement);
+        replaced.insert(name, replacement);
+    }
+    Ok(())
+}
+
+/// If every part sent to the workflow at idx ends up with the same outcome, return it
+fn constant_outcome<const N: usize>(graph: &WorkflowGraph<'_>, idx: usize) -> Option<AcceptReject> {
+    let everything = NDRange::new([Interval::new(0, usize::MAX); N]);
+    let mut outcomes = split_range_through_graph(everything, Either::Right(idx), graph)
+        .map(|(_, acc_rej)| acc_rej);
+    let first = outcomes.next()?;
+    outcomes.all(|acc_rej| acc_rej == first).then_some(first)
+}
+
+fn inline_single_use_workflows(workflows: &mut Vec<Workflow<'_>>) {
+    loop {
+        let mut uses: HashMap<&str, Vec<(usize, Option<usize>)>> = HashMap::new();
+        for (idx, workflow) in workflows.iter().enumerate() {
+            for (rule_idx, rule) in workflow.rules.iter().enumerate() {
+                if let Either::Right(name) = rule.dst {
+                    uses.entry(name).or_default().push((idx, Some(rule_idx)));
+                }
+            }
+            if let Either::Right(name) = workflow.default {
+                uses.entry(name).or_default().push((idx, None));
+            }
+        }
+
+        // Only defaults can be inlined. A rule that sends parts to another workflow would need
+        // the conditions of both to be and-ed together, which a single rule can't express
+        let inlinable = workflows.iter().enumerate().find_map(|(idx, workflow)| {
+            match uses.get(workflow.name).map(Vec::as_slice) {
+                Some(&[(user, None)]) if workflow.name != "in" && user != idx => Some((idx, user)),
+                _ => None,
+            }
+        });
+        let Some((idx, user)) = inlinable else {
+            return;
+        };
+        let inlined = workflows[idx].clone();
+        workflows[user].rules.extend(inlined.rules);
+        workflows[user].default = inlined.default;
+        workflows.remove(idx);
+    }
+}
+
+fn remove_unreachable_workflows<'a>(workflows: &mut Vec<Workflow<'a>>) {
+    let mut reachable: HashSet<&'a str> = HashSet::new();
+    let mut queue: VecDeque<&'a str> = VecDeque::from(["in"]);
+    while let Some(name) = queue.pop_front() {
+        if !reachable.insert(name) {
+            continue;
+        }
+        if let Some(workflow) = workflows.iter().find(|wf| wf.name == name) {
+            queue.extend(
+                workflow
+                    .rules
+                    .iter()
+                    .map(|rule| rule.dst)
+                    .chain(std::iter::once(workflow.default))
+                    .filter_map(|dst| dst.right()),
+            );
+        }
+    }
+    workflows.retain(|wf| reachable.contains(wf.name));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use nom::{character::complete::newline, multi::separated_list1};
+
+    const XMAS: [&str; 4] = ["x", "m", "a", "s"];
+
+    fn minimise_text(input: &str) -> Vec<String> {
+        let (_, workflows) = separated_list1(newline, parse::parse_workflow)(input).unwrap();
+        let minimised = minimise(&workflows, &XMAS).unwrap();
+        assert!(same_accepted_region(
+            &compile(&workflows, &XMAS).unwrap(),
+            &compile(&minimised, &XMAS).unwrap(),
+            &[Interval::new(1, 4001); 4]
+        ));
+        minimised.iter().map(|wf| wf.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_rules() {
+        assert_eq!(
+            minimise_text("in{x<5:A,x<10:A,m>3:R,R}"),
+            vec!["in{x<10:A,R}"]
+        );
+        assert_eq!(minimise_text("in{x>5:A,x==5:A,R}"), vec!["in{x>4:A,R}"]);
+        assert_eq!(minimise_text("in{x!=5:A,x==5:A,R}"), vec!["in{A}"]);
+        assert_eq!(
+            minimise_text("in{x<5:A,x>5:A,m<3:A,R}"),
+            vec!["in{x!=5:A,m<3:A,R}"]
+        );
+        assert_eq!(
+            minimise_text("in{x<5:A,x>10:A,m<3:A,R}"),
+            vec!["in{x<5:A,x>10:A,m<3:A,R}"]
+        );
+        // m<5 can't fire after the rules on x, and then x>=10:R is the same as the default
+        assert_eq!(
+            minimise_text("in{x<10:A,x>=10:R,m<5:A,R}"),
+            vec!["in{x<10:A,R}"]
+        );
+        // nothing reaches R, so the last rule that can fire takes its place
+        assert_eq!(minimise_text("in{x<10:A,x>5:R,R}"), vec!["in{x<10:A,R}"]);
+        assert_eq!(minimise_text("in{x<10:A,x>5:A,R}"), vec!["in{A}"]);
+    }
+
+    #[test]
+    fn test_inline_and_collapse() {
+        assert_eq!(
+            minimise_text("in{x<5:A,foo}\nfoo{m<3:R,bar}\nbar{a>100:R,s>1:A,A}"),
+            vec!["in{x<5:A,m<3:R,a>100:R,A}"]
+        );
+        assert_eq!(
+            minimise_text("in{x<5:foo,bar}\nfoo{m>3:A,A}\nbar{a<3:R,s<2:foo,baz}\nbaz{R}"),
+            vec!["in{x<5:A,a<3:R,s<2:A,R}"]
+        );
+    }
+
+    #[test]
+    fn test_example() {
+        let input = indoc! {"
+            px{a<2006:qkq,m>2090:A,rfg}
+            pv{a>1716:R,A}
+            lnx{m>1548:A,A}
+            rfg{s<537:gd,x>2440:R,A}
+            qs{s>3448:A,lnx}
+            qkq{x<1416:A,crn}
+            crn{x>2662:A,R}
+            in{s<1351:px,qqz}
+            qqz{s>2770:qs,m<1801:hdj,R}
+            gd{a>3333:R,R}
+            hdj{m>838:A,pv}"};
+        assert_eq!(
+            minimise_text(input),
+            vec![
+                "px{a<2006:qkq,m>2090:A,s<537:R,x>2440:R,A}",
+                "qkq{x<1416:A,x>2662:A,R}",
+                "in{s<1351:px,s>2770:A,m<1801:hdj,R}",
+                "hdj{m>838:A,a>1716:R,A}",
+            ]
+        );
+    }
+}
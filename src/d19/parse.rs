@@ -9,7 +9,7 @@ use nom::{
     combinator::all_consuming,
     error::{ErrorKind, ParseError},
     multi::{many0, separated_list1},
-    sequence::tuple,
+    sequence::{terminated, tuple},
     IResult,
 };
 
@@ -34,23 +34,31 @@ impl From<nom::Err<nom::error::Error<&str>>> for CustomError {
 
 type ParseResult<'a, T> = IResult<&'a str, T, CustomError>;
 
+/// (workflows, attribute names, parts)
+type ParsedInput<'a> = (Vec<Workflow<'a>>, Vec<&'a str>, Vec<Part<'a>>);
+
 /// Parse a rule from a workflow. E.g. a<2006:qkq or m>2090:A from px{a<2006:qkq,m>2090:A,rfg}
-pub fn parse_rule<'a>(input: &'a str) -> ParseResult<WorkflowRule<'a>> {
+pub fn parse_rule<'a>(input: &'a str) -> ParseResult<'a, WorkflowRule<'a>> {
     let (input, attr) = alpha1(input)?;
-    let (input, gtlt) = alt((tag("<"), tag(">")))(input)?;
+    // the two character ops have to come first so that <= isn't parsed as <
+    let (input, op) = alt((
+        tag("<="),
+        tag(">="),
+        tag("=="),
+        tag("!="),
+        tag("<"),
+        tag(">"),
+    ))(input)?;
     let (input, val) = complete::u64(input)?;
     let (input, _) = tag(":")(input)?;
     let (input, dst) = alpha1(input)?;
-    let attr = match attr {
-        "x" => PartAttr::X,
-        "m" => PartAttr::M,
-        "a" => PartAttr::A,
-        "s" => PartAttr::S,
-        _ => Err(nom::Err::Error(CustomError("oh no".to_owned())))?,
-    };
-    let gtlt = match gtlt {
-        ">" => GTorLT::GT,
-        "<" => GTorLT::LT,
+    let op = match op {
+        "<" => Op::LT,
+        ">" => Op::GT,
+        "<=" => Op::LE,
+        ">=" => Op::GE,
+        "==" => Op::EQ,
+        "!=" => Op::NE,
         _ => Err(nom::Err::Error(CustomError("oh no".to_owned())))?,
     };
     let dst = match dst {
@@ -62,21 +70,19 @@ pub fn parse_rule<'a>(input: &'a str) -> ParseResult<WorkflowRule<'a>> {
     Ok((
         input,
         WorkflowRule {
-            attr,
-            gtlt,
-            val,
+            cond: Condition { attr, op, val },
             dst,
         },
     ))
 }
 
-/// Parse a workflow. E.g. px{a<2006:qkq,m>2090:A,rfg}
-pub fn parse_workflow<'a>(input: &'a str) -> ParseResult<Workflow<'a>> {
+/// Parse a workflow. E.g. px{a<2006:qkq,m>2090:A,rfg}. A workflow can also have no rules, like
+/// px{rfg}
+pub fn parse_workflow<'a>(input: &'a str) -> ParseResult<'a, Workflow<'a>> {
     let (input, name) = alpha1(input)?;
     let (input, _) = tag("{")(input)?;
 
-    let (input, rules) = separated_list1(tag(","), parse_rule)(input)?;
-    let (input, _) = tag(",")(input)?;
+    let (input, rules) = many0(terminated(parse_rule, tag(",")))(input)?;
     let (input, default) = alpha1(input)?;
     let (input, _) = tag("}")(input)?;
     let default = match default {
@@ -94,54 +100,187 @@ pub fn parse_workflow<'a>(input: &'a str) -> ParseResult<Workflow<'a>> {
     ))
 }
 
-/// Parse a part. E.g. {x=787,m=2655,a=1222,s=2876}
-pub fn parse_part<'a>(input: &'a str) -> ParseResult<Part> {
+/// Parse a part. E.g. {x=787,m=2655,a=1222,s=2876}. Any attribute names are allowed, but each can
+/// only appear once
+pub fn parse_part<'a>(input: &'a str) -> ParseResult<'a, Part<'a>> {
     let (input, _) = tag("{")(input)?;
-    // TODO: would be nice if you didn't allocate a list
-    let (input, attrs_list) =
+    let (input, attrs) =
         separated_list1(tag(","), |input: &'a str| -> ParseResult<(&str, usize)> {
-            let (input, (name, _, val)) = tuple((
-                alt((tag("x"), tag("m"), tag("a"), tag("s"))),
-                tag("="),
-                complete::u64,
-            ))(input)?;
+            let (input, (name, _, val)) = tuple((alpha1, tag("="), complete::u64))(input)?;
             Ok((input, (name, val as usize)))
         })(input)?;
-
-    if attrs_list.len() != 4 {
-        return Err(nom::Err::Error(CustomError("too long".to_owned())));
-    }
-
     let (input, _) = tag("}")(input)?;
 
-    let (x, m, a, s): (usize, usize, usize, usize) = {
-        let (mut x, mut m, mut a, mut s) = (None, None, None, None);
-        for (ident, val) in attrs_list {
-            match ident {
-                "x" => x = Some(val),
-                "m" => m = Some(val),
-                "a" => a = Some(val),
-                "s" => s = Some(val),
-                _ => return Err(nom::Err::Error(CustomError("unknown char".to_owned()))),
-            }
+    for (i, (name, _)) in attrs.iter().enumerate() {
+        if attrs[..i].iter().any(|(other, _)| other == name) {
+            return Err(nom::Err::Error(CustomError(format!(
+                "Duplicate attribute: {}",
+                name
+            ))));
         }
-        (
-            x.ok_or_else(|| nom::Err::Error(CustomError("Missing attribute: x".to_owned())))?,
-            m.ok_or_else(|| nom::Err::Error(CustomError("Missing attribute: m".to_owned())))?,
-            a.ok_or_else(|| nom::Err::Error(CustomError("Missing attribute: a".to_owned())))?,
-            s.ok_or_else(|| nom::Err::Error(CustomError("Missing attribute: s".to_owned())))?,
-        )
-    };
-    Ok((input, Part { x, m, a, s }))
+    }
+    Ok((input, Part { attrs }))
 }
 
-pub fn parse<'a>(input: &'a str) -> Result<(Vec<Workflow<'a>>, Vec<Part>), nom::Err<CustomError>> {
-    let (_, result) = all_consuming(|input| {
+/// Parse the workflows and parts. Also returns the attribute names the parts have, in the order
+/// the first part lists them. Every part has to have the same attributes, but they can come in any
+/// order and each part keeps its own
+pub fn parse<'a>(input: &'a str) -> Result<ParsedInput<'a>, nom::Err<CustomError>> {
+    let (_, (workflows, parts)) = all_consuming(|input| {
         let (input, workflows) = separated_list1(newline, parse_workflow)(input)?;
         let (input, _) = many0(newline)(input)?;
         let (input, parts) = separated_list1(newline, parse_part)(input)?;
         let (input, _) = many0(newline)(input)?;
         Ok((input, (workflows, parts)))
     })(input)?;
-    Ok(result)
+
+    let attrs: Vec<&str> = parts[0].attrs.iter().map(|&(name, _)| name).collect();
+    for part in &parts {
+        if part.attrs.len() != attrs.len() {
+            return Err(nom::Err::Error(CustomError(format!(
+                "Expected {} attributes but got {}",
+                attrs.len(),
+                part.attrs.len()
+            ))));
+        }
+        // parts can't repeat an attribute, so having the same number means this is enough
+        if let Some(name) = attrs.iter().find(|&&name| part.get(name).is_none()) {
+            return Err(nom::Err::Error(CustomError(format!(
+                "Missing attribute: {}",
+                name
+            ))));
+        }
+    }
+    Ok((workflows, attrs, parts))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use itertools::Itertools;
+
+    /// Render parsed input back into the puzzle's input format
+    fn print(workflows: &[Workflow<'_>], parts: &[Part<'_>]) -> String {
+        let workflows = workflows.iter().map(|wf| wf.to_string()).join("\n");
+        let parts = parts.iter().map(|part| part.to_string()).join("\n");
+        format!("{}\n\n{}\n", workflows, parts)
+    }
+
+    #[test]
+    fn test_round_trip_example() {
+        let input = indoc! {"
+            px{a<2006:qkq,m>2090:A,rfg}
+            pv{a>1716:R,A}
+            in{s<=1350:px,x!=5:R,qqz}
+            qqz{s>=2771:qs,m==1800:hdj,R}
+            qs{A}
+
+            {x=787,m=2655,a=1222,s=2876}
+            {x=1679,m=44,a=2067,s=496}
+        "};
+        let (workflows, attrs, parts) = parse(input).unwrap();
+        assert_eq!(print(&workflows, &parts), input);
+        assert_eq!(
+            parse(&print(&workflows, &parts)),
+            Ok((workflows, attrs, parts))
+        );
+    }
+
+    #[test]
+    fn test_round_trip_generated() {
+        // Small xorshift so the generated inputs are the same on every run
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut rand = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+
+        let attr_names = ["x", "m", "a", "s", "rho", "theta"];
+        let wf_names = ["in", "px", "qqz", "hdj", "lnx", "crn"];
+        let ops = ["<", ">", "<=", ">=", "==", "!="];
+        for _ in 0..200 {
+            let num_attrs = 1 + rand(attr_names.len());
+            let attrs = &attr_names[..num_attrs];
+            let dst = |rand: &mut dyn FnMut(usize) -> usize| match rand(wf_names.len() + 2) {
+                0 => "A",
+                1 => "R",
+                n => wf_names[n - 2],
+            };
+
+            let workflows = wf_names
+                .iter()
+                .map(|name| {
+                    let rules = (0..rand(5))
+                        .map(|_| {
+                            format!(
+                                "{}{}{}:{},",
+                                attrs[rand(num_attrs)],
+                                ops[rand(ops.len())],
+                                rand(4001),
+                                dst(&mut rand)
+                            )
+                        })
+                        .collect::<String>();
+                    format!("{}{{{}{}}}", name, rules, dst(&mut rand))
+                })
+                .join("\n");
+            // every part after the first lists the attributes in a random order
+            let parts = (0..1 + rand(4))
+                .map(|i| {
+                    let mut attrs = attrs.to_vec();
+                    if i > 0 {
+                        for j in (1..attrs.len()).rev() {
+                            attrs.swap(j, rand(j + 1));
+                        }
+                    }
+                    let vals = attrs
+                        .iter()
+                        .map(|attr| format!("{}={}", attr, rand(4001)))
+                        .join(",");
+                    format!("{{{}}}", vals)
+                })
+                .join("\n");
+            let input = format!("{}\n\n{}\n", workflows, parts);
+
+            let (workflows, attrs, parts) = parse(&input).unwrap();
+            let printed = print(&workflows, &parts);
+            assert_eq!(printed, input);
+            assert_eq!(parse(&printed), Ok((workflows, attrs, parts)));
+        }
+    }
+
+    #[test]
+    fn test_mixed_order() {
+        let input = indoc! {"
+            in{x<10:A,s>5:R,A}
+
+            {x=1,m=2,a=3,s=4}
+            {s=40,a=30,x=10,m=20}
+            {m=200,x=100,s=400,a=300}
+        "};
+        let (workflows, attrs, parts) = parse(input).unwrap();
+        assert_eq!(attrs, vec!["x", "m", "a", "s"]);
+        assert_eq!(print(&workflows, &parts), input);
+        assert_eq!(parts[1].get("x"), Some(10));
+        assert_eq!(parts[2].get("s"), Some(400));
+
+        // the compiled graph reads each part's values by name, so the order doesn't change what
+        // happens to it
+        let graph = compile(&workflows, &attrs).unwrap();
+        assert_eq!(graph.values(&parts[2]).as_slice(), &[100, 200, 300, 400]);
+        let outcomes: Vec<_> = parts.iter().map(|part| graph.send_part(part)).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                AcceptReject::Accept,
+                AcceptReject::Reject,
+                AcceptReject::Reject
+            ]
+        );
+
+        assert!(parse("in{A}\n\n{x=1,m=2}\n{m=2,a=1}\n").is_err());
+    }
 }
@@ -0,0 +1,401 @@
+use super::*;
+
+use module::Kind;
+use std::fmt;
+
+// The simulator in main is easy to follow but slow: every pulse looks modules up by name in a
+// HashMap, allocates a Vec for the pulses it makes, and conjunctions search their inputs linearly.
+// This one gives every module a number up front, so a press is just array indexing and bit
+// twiddling:
+//
+//      flip-flops are bits in one bitset
+//      conjunction memory is a u64 per conjunction, one bit per input, so "all high" is one compare
+//      outgoing edges are one flat array, with the bit each edge sets in its conjunction baked in
+//      pulses go through one ring buffer (a VecDeque) that's reused between presses
+//
+// Only the built-in module kinds are supported, though they can be registered under any prefix.
+// Those send at most one pulse for each one they receive, always to every output, and ignore the
+// button, so a module here is one bit of behaviour rather than a call to Module::receive and
+// Module::on_press like in push_button.
+
+/// Interned module id: the module's index in module_names
+pub type ModuleId = u32;
+
+/// The source of the first pulse of every press
+pub const BUTTON: ModuleId = ModuleId::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompiledPulse {
+    pub src: ModuleId,
+    pub dst: ModuleId,
+    pub high_or_low: bool,
+}
+
+/// What a module id is, with where its state lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Broadcaster,
+    /// Index of the module's bit in CompiledCircuit::flip_flops
+    FlipFlop(usize),
+    /// Index into CompiledCircuit::conj_memory and conj_full
+    Conjunction(usize),
+    /// A destination that isn't a module, like rx
+    Sink,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    dst: ModuleId,
+    /// The bit this edge sets in dst's memory, if dst is a conjunction
+    input_mask: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CompileError<'a> {
+    /// The module's kind isn't one of the built in ones, so it might send several pulses, pick
+    /// its outputs or act on a button press
+    UnsupportedModule {
+        module: ModuleIdent<'a>,
+        prefix: &'a str,
+    },
+    /// Conjunction memory is a u64, so conjunctions can have at most 64 inputs
+    TooManyInputs(ModuleIdent<'a>),
+    MissingBroadcaster,
+}
+
+impl<'a> fmt::Display for CompileError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UnsupportedModule { module, prefix } => write!(
+                f,
+                "module {} has type {:?}, which the compiled simulator doesn't support",
+                module, prefix
+            ),
+            CompileError::TooManyInputs(module) => {
+                write!(f, "conjunction {} has more than 64 inputs", module)
+            }
+            CompileError::MissingBroadcaster => write!(f, "there's no broadcaster module"),
+        }
+    }
+}
+
+impl<'a> Error for CompileError<'a> {}
+
+#[derive(Debug, Clone)]
+pub struct CompiledCircuit {
+    nodes: Vec<Node>,
+    /// The outgoing edges of module id are edges[edge_starts[id]..edge_starts[id + 1]]
+    edge_starts: Vec<usize>,
+    edges: Vec<Edge>,
+    broadcaster: ModuleId,
+
+    flip_flops: Vec<u64>,
+    conj_memory: Vec<u64>,
+    /// What conj_memory is when every input's most recent pulse was high
+    conj_full: Vec<u64>,
+    /// Pulses sent but not delivered yet, with the bit each sets if its dst is a conjunction. Empty
+    /// between presses, but it keeps its capacity so presses don't allocate
+    queue: VecDeque<(CompiledPulse, u64)>,
+}
+
+/// Every module and sink in mmap, sorted by name. A module's id is its index in this
+pub fn module_names<'a>(mmap: &ModuleMap<'a>) -> Vec<ModuleIdent<'a>> {
+    let mut names: Vec<ModuleIdent<'a>> = mmap
+        .iter()
+        .flat_map(|(name, (_, dsts))| std::iter::once(name).chain(dsts))
+        .copied()
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+impl CompiledCircuit {
+    /// Number every module in mmap, looking up their kinds in registry
+    pub fn compile<'a>(
+        registry: &Registry<'_>,
+        mmap: &ModuleMap<'a>,
+    ) -> Result<Self, CompileError<'a>> {
+        let names = module_names(mmap);
+        let id = |name: &str| names.binary_search(&name).unwrap() as ModuleId;
+
+        let mut num_flip_flops = 0;
+        let mut num_conjunctions = 0;
+        let nodes = names
+            .iter()
+            .map(|&name| {
+                let Some((prefix, _)) = mmap.get(name) else {
+                    return Ok(Node::Sink);
+                };
+                match registry.kind(prefix) {
+                    Some(Kind::Broadcaster) => Ok(Node::Broadcaster),
+                    Some(Kind::FlipFlop) => {
+                        num_flip_flops += 1;
+                        Ok(Node::FlipFlop(num_flip_flops - 1))
+                    }
+                    Some(Kind::Conjunction) => {
+                        num_conjunctions += 1;
+                        Ok(Node::Conjunction(num_conjunctions - 1))
+                    }
+                    None => Err(CompileError::UnsupportedModule {
+                        module: name,
+                        prefix,
+                    }),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // each conjunction gives its inputs bits in the order they're found
+        let mut conj_inputs: Vec<Vec<ModuleId>> = vec![vec![]; num_conjunctions];
+        let mut edge_starts = vec![0];
+        let mut edges = Vec::new();
+        for &name in &names {
+            for &dst in mmap
+                .get(name)
+                .map(|(_, dsts)| dsts.as_slice())
+                .unwrap_or(&[])
+            {
+                let dst_id = id(dst);
+                let input_mask = match nodes[dst_id as usize] {
+                    Node::Conjunction(conj) => {
+                        let inputs = &mut conj_inputs[conj];
+                        let bit = match inputs.iter().position(|&input| input == id(name)) {
+                            Some(bit) => bit,
+                            None => {
+                                inputs.push(id(name));
+                                inputs.len() - 1
+                            }
+                        };
+                        if bit >= 64 {
+                            return Err(CompileError::TooManyInputs(dst));
+                        }
+                        1 << bit
+                    }
+                    _ => 0,
+                };
+                edges.push(Edge {
+                    dst: dst_id,
+                    input_mask,
+                });
+            }
+            edge_starts.push(edges.len());
+        }
+
+        let broadcaster = names
+            .binary_search(&"broadcaster")
+            .map_err(|_| CompileError::MissingBroadcaster)? as ModuleId;
+        let conj_full = conj_inputs
+            .iter()
+            .map(|inputs| match inputs.len() {
+                64 => u64::MAX,
+                len => (1 << len) - 1,
+            })
+            .collect();
+
+        Ok(CompiledCircuit {
+            nodes,
+            edge_starts,
+            edges,
+            broadcaster,
+            flip_flops: vec![0; num_flip_flops.div_ceil(64)],
+            conj_memory: vec![0; num_conjunctions],
+            conj_full,
+            queue: VecDeque::new(),
+        })
+    }
+
+    /// Press the button, calling visit with every pulse in the order they're sent
+    pub fn press_with(&mut self, mut visit: impl FnMut(CompiledPulse)) {
+        self.queue.push_back((
+            CompiledPulse {
+                src: BUTTON,
+                dst: self.broadcaster,
+                high_or_low: false,
+            },
+            0,
+        ));
+
+        while let Some((pulse, input_mask)) = self.queue.pop_front() {
+            visit(pulse);
+            let dst = pulse.dst as usize;
+            let out = match self.nodes[dst] {
+                Node::Broadcaster => pulse.high_or_low,
+                Node::FlipFlop(bit) => {
+                    if pulse.high_or_low {
+                        continue;
+                    }
+                    let word = &mut self.flip_flops[bit / 64];
+                    *word ^= 1 << (bit % 64);
+                    *word >> (bit % 64) & 1 == 1
+                }
+                Node::Conjunction(conj) => {
+                    let memory = &mut self.conj_memory[conj];
+                    if pulse.high_or_low {
+                        *memory |= input_mask;
+                    } else {
+                        *memory &= !input_mask;
+                    }
+                    *memory != self.conj_full[conj]
+                }
+                Node::Sink => continue,
+            };
+            for edge in &self.edges[self.edge_starts[dst]..self.edge_starts[dst + 1]] {
+                self.queue.push_back((
+                    CompiledPulse {
+                        src: pulse.dst,
+                        dst: edge.dst,
+                        high_or_low: out,
+                    },
+                    edge.input_mask,
+                ));
+            }
+        }
+    }
+
+    /// Press the button, returning the number of (low, high) pulses sent
+    pub fn press(&mut self) -> (usize, usize) {
+        let (mut low, mut high) = (0, 0);
+        self.press_with(|pulse| {
+            if pulse.high_or_low {
+                high += 1;
+            } else {
+                low += 1;
+            }
+        });
+        (low, high)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    /// Press the button presses times with both simulators, checking they send the same pulses
+    fn assert_equivalent(input: &str, presses: usize) {
+        let registry = Registry::default();
+        let (mmap, mut state) = circuit(input);
+        let connections = to_connections(&mmap);
+        let mut circuit = CompiledCircuit::compile(&registry, &mmap).unwrap();
+        let names = module_names(&mmap);
+        let name = |id| {
+            if id == BUTTON {
+                "button"
+            } else {
+                names[id as usize]
+            }
+        };
+
+        for press in 1..=presses {
+            let expected: Vec<_> = push_button(&mut state, &connections)
+                .map(|pulse| (pulse.src, pulse.dst, pulse.high_or_low))
+                .collect();
+            let mut pulses = Vec::new();
+            circuit.press_with(|pulse| pulses.push(pulse));
+            let actual: Vec<_> = pulses
+                .iter()
+                .map(|pulse| (name(pulse.src), name(pulse.dst), pulse.high_or_low))
+                .collect();
+            assert_eq!(actual, expected, "press {}", press);
+        }
+    }
+
+    #[test]
+    fn test_equivalent_examples() {
+        let example1 = indoc! {"
+            broadcaster -> a, b, c
+            %a -> b
+            %b -> c
+            %c -> inv
+            &inv -> a
+        "};
+        let example2 = indoc! {"
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> output
+        "};
+        assert_equivalent(example1, 1000);
+        assert_equivalent(example2, 1000);
+
+        let registry = Registry::default();
+        let (_, mmap) = parse(&registry, example1).unwrap();
+        let mut circuit = CompiledCircuit::compile(&registry, &mmap).unwrap();
+        let (low, high) = (0..1000).fold((0, 0), |(low, high), _| {
+            let (l, h) = circuit.press();
+            (low + l, high + h)
+        });
+        assert_eq!(low * high, 32000000);
+    }
+
+    #[test]
+    fn test_equivalent_counters() {
+        let input = indoc! {"
+            broadcaster -> aba, bba
+            %aba -> abb, acon
+            %abb -> acon
+            &acon -> aba, qr
+            &qr -> hub
+            %bba -> bbb, bcon
+            %bbb -> bbc
+            %bbc -> bcon
+            &bcon -> bba, bbb, lk
+            &lk -> hub
+            &hub -> rx
+        "};
+        assert_equivalent(input, 100);
+    }
+
+    #[test]
+    fn test_wide_flip_flops() {
+        // enough flip-flops to need more than one word of the bitset
+        let names: Vec<String> = (0..70)
+            .map(|i| format!("f{}{}", (b'a' + i / 26) as char, (b'a' + i % 26) as char))
+            .collect();
+        let mut input = format!("broadcaster -> {}\n", names[0]);
+        for pair in names.windows(2) {
+            input += &format!("%{} -> {}\n", pair[0], pair[1]);
+        }
+        input += &format!("%{} -> end\n", names[names.len() - 1]);
+        assert_equivalent(&input, 300);
+    }
+
+    #[test]
+    fn test_registered_kinds() {
+        // built-in kinds compile under any prefix they're registered with
+        let mut registry = Registry::default();
+        registry.register("^", Some(Kind::FlipFlop), || {
+            Box::<module::FlipFlop>::default()
+        });
+        let presses = |input| {
+            let (_, mmap) = parse(&registry, input).unwrap();
+            let mut circuit = CompiledCircuit::compile(&registry, &mmap).unwrap();
+            (0..8).map(|_| circuit.press()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            presses("broadcaster -> a\n^a -> b\n^b -> out\n"),
+            presses("broadcaster -> a\n%a -> b\n%b -> out\n")
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let mut registry = Registry::default();
+        registry.register("^", None, || Box::<module::FlipFlop>::default());
+        let (_, mmap) = parse(&registry, "broadcaster -> a\n^a -> b\n").unwrap();
+        assert_eq!(
+            CompiledCircuit::compile(&registry, &mmap).unwrap_err(),
+            CompileError::UnsupportedModule {
+                module: "a",
+                prefix: "^"
+            }
+        );
+
+        let (_, mmap) = parse(&registry, "%a -> b\n").unwrap();
+        assert_eq!(
+            CompiledCircuit::compile(&registry, &mmap).unwrap_err(),
+            CompileError::MissingBroadcaster
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use super::*;
+
+use module::Kind;
+use std::fmt;
+
+// Part 2 relies on the shape of the puzzle input rather than simulating until rx gets a low pulse
+// (which takes hundreds of trillions of button presses):
+//
+//      rx has exactly one input, a conjunction (the feeder)
+//      the feeder sends low to rx once every one of its inputs has most recently sent it high
+//      each input of the feeder sends high on a fixed cycle of button presses
+//
+// So find the presses each input sends high on, and work out the first press they all line up
+
+/// The button presses on which a module sends a high pulse: offset, offset + period,
+/// offset + 2 * period, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub offset: usize,
+    pub period: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CycleError<'a> {
+    /// Nothing sends pulses to rx
+    MissingRx,
+    /// rx has to have exactly one input
+    MultipleFeeders(Vec<&'a str>),
+    /// The input to rx has to be a conjunction
+    FeederNotConjunction(&'a str),
+    /// This input of the feeder didn't send high at least 3 times in this many presses
+    NoCycle { module: &'a str, presses: usize },
+    /// This input of the feeder sent high, but not on a fixed cycle
+    NotPeriodic {
+        module: &'a str,
+        presses: Vec<usize>,
+    },
+    /// The cycles never all line up
+    NoSolution,
+}
+
+impl<'a> fmt::Display for CycleError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CycleError::MissingRx => write!(f, "no module sends pulses to rx"),
+            CycleError::MultipleFeeders(feeders) => write!(
+                f,
+                "expected rx to have one input but it has {}",
+                feeders.join(", ")
+            ),
+            CycleError::FeederNotConjunction(feeder) => {
+                write!(
+                    f,
+                    "expected the input to rx ({}) to be a conjunction",
+                    feeder
+                )
+            }
+            CycleError::NoCycle { module, presses } => write!(
+                f,
+                "{} didn't send enough high pulses to find a cycle in {} presses",
+                module, presses
+            ),
+            CycleError::NotPeriodic { module, presses } => write!(
+                f,
+                "{} sent high pulses on presses {:?}, which isn't a fixed cycle",
+                module, presses
+            ),
+            CycleError::NoSolution => write!(f, "the input cycles never line up"),
+        }
+    }
+}
+
+impl<'a> Error for CycleError<'a> {}
+
+/// Find the conjunction that feeds rx, and its inputs
+pub fn find_feeder<'a>(
+    registry: &Registry<'_>,
+    mmap: &ModuleMap<'a>,
+    connections: &Connections<'a, '_>,
+) -> Result<(ModuleIdent<'a>, Vec<ModuleIdent<'a>>), CycleError<'a>> {
+    let rev_connections = reverse_map(connections);
+    let feeder = match rev_connections.get("rx").map(Vec::as_slice) {
+        None | Some([]) => return Err(CycleError::MissingRx),
+        Some(&[feeder]) => feeder,
+        Some(feeders) => return Err(CycleError::MultipleFeeders(feeders.to_vec())),
+    };
+    let kind = mmap
+        .get(feeder)
+        .and_then(|(prefix, _)| registry.kind(prefix));
+    if kind != Some(Kind::Conjunction) {
+        return Err(CycleError::FeederNotConjunction(feeder));
+    }
+    let mut inputs = rev_connections.get(feeder).cloned().unwrap_or_default();
+    inputs.sort();
+    Ok((feeder, inputs))
+}
+
+/// Press the button up to max_presses times, watching the high pulses each of inputs sends to
+/// feeder, and work out the cycle for each input
+pub fn detect_cycles<'a>(
+    mut state: AllState<'a>,
+    connections: &Connections<'a, '_>,
+    feeder: ModuleIdent<'a>,
+    inputs: &[ModuleIdent<'a>],
+    max_presses: usize,
+) -> Result<Vec<Cycle>, CycleError<'a>> {
+    let mut high_presses: Vec<Vec<usize>> = vec![vec![]; inputs.len()];
+    for press in 1..=max_presses {
+        for pulse in push_button(&mut state, connections) {
+            if pulse.dst != feeder || !pulse.high_or_low {
+                continue;
+            }
+            if let Some(idx) = inputs.iter().position(|&input| input == pulse.src) {
+                if high_presses[idx].last() != Some(&press) {
+                    high_presses[idx].push(press);
+                }
+            }
+        }
+        // two presses give a period, the third checks it
+        if high_presses.iter().all(|presses| presses.len() >= 3) {
+            break;
+        }
+    }
+
+    inputs
+        .iter()
+        .zip(high_presses)
+        .map(|(&module, presses)| match presses[..] {
+            [first, second, third, ..] if third - second == second - first => Ok(Cycle {
+                offset: first,
+                period: second - first,
+            }),
+            [_, _, _, ..] => Err(CycleError::NotPeriodic { module, presses }),
+            _ => Err(CycleError::NoCycle {
+                module,
+                presses: max_presses,
+            }),
+        })
+        .collect()
+}
+
+/// The first press that's on every cycle, using the Chinese remainder theorem (the periods don't
+/// have to be coprime)
+pub fn combine_cycles(cycles: &[Cycle]) -> Option<usize> {
+    // Invariant: the presses on every cycle so far are exactly those congruent to rem mod modulus
+    let (mut rem, mut modulus): (i128, i128) = (0, 1);
+    for cycle in cycles {
+        let (offset, period) = (cycle.offset as i128, cycle.period as i128);
+        let (gcd, inv, _) = extended_gcd(modulus, period);
+        if (offset - rem) % gcd != 0 {
+            return None;
+        }
+        // rem + modulus * k = offset (mod period)
+        let k = ((offset - rem) / gcd * inv).rem_euclid(period / gcd);
+        rem += modulus * k;
+        modulus *= period / gcd;
+        rem = rem.rem_euclid(modulus);
+    }
+
+    // cycles only start at their offset
+    let min_press = cycles.iter().map(|cycle| cycle.offset as i128).max()?;
+    if rem < min_press {
+        rem += (min_press - rem + modulus - 1) / modulus * modulus;
+    }
+    usize::try_from(rem).ok()
+}
+
+/// (gcd(a, b), x, y) where a * x + b * y = gcd(a, b)
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (gcd, x, y) = extended_gcd(b, a % b);
+        (gcd, y, x - a / b * y)
+    }
+}
+
+/// Work out the first press that sends a low pulse to rx
+pub fn part2<'a>(
+    registry: &Registry<'_>,
+    state: &AllState<'a>,
+    mmap: &ModuleMap<'a>,
+    connections: &Connections<'a, '_>,
+    max_presses: usize,
+) -> Result<(Vec<(ModuleIdent<'a>, Cycle)>, usize), CycleError<'a>> {
+    let (feeder, inputs) = find_feeder(registry, mmap, connections)?;
+    let cycles = detect_cycles(state.clone(), connections, feeder, &inputs, max_presses)?;
+    let presses = combine_cycles(&cycles).ok_or(CycleError::NoSolution)?;
+    Ok((inputs.into_iter().zip(cycles).collect(), presses))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_combine_cycles() {
+        let cycle = |offset, period| Cycle { offset, period };
+        assert_eq!(combine_cycles(&[cycle(3, 3), cycle(5, 5)]), Some(15));
+        assert_eq!(combine_cycles(&[cycle(2, 3), cycle(3, 5)]), Some(8));
+        assert_eq!(combine_cycles(&[cycle(4, 4), cycle(6, 6)]), Some(12));
+        assert_eq!(combine_cycles(&[cycle(1, 4), cycle(2, 6)]), None);
+        // 14 is the first that fits both, but the second cycle only starts at 26
+        assert_eq!(combine_cycles(&[cycle(2, 4), cycle(26, 6)]), Some(26));
+        assert_eq!(combine_cycles(&[]), None);
+    }
+
+    #[test]
+    fn test_part2() {
+        // Two counters that reset after 3 and 5 presses, sending high to hub through qr and lk
+        let input = indoc! {"
+            broadcaster -> aba, bba
+            %aba -> abb, acon
+            %abb -> acon
+            &acon -> aba, qr
+            &qr -> hub
+            %bba -> bbb, bcon
+            %bbb -> bbc
+            %bbc -> bcon
+            &bcon -> bba, bbb, lk
+            &lk -> hub
+            &hub -> rx
+        "};
+        let registry = Registry::default();
+        let (mmap, state) = circuit(input);
+        let connections = to_connections(&mmap);
+
+        let (cycles, presses) = part2(&registry, &state, &mmap, &connections, 100).unwrap();
+        assert_eq!(
+            cycles,
+            vec![
+                (
+                    "lk",
+                    Cycle {
+                        offset: 5,
+                        period: 5
+                    }
+                ),
+                (
+                    "qr",
+                    Cycle {
+                        offset: 3,
+                        period: 3
+                    }
+                ),
+            ]
+        );
+        assert_eq!(presses, 15);
+
+        // check against actually pressing the button
+        let mut pressed_state = state.clone();
+        let first_low_rx = (1..)
+            .find(|_| {
+                push_button(&mut pressed_state, &connections)
+                    .any(|pulse| pulse.dst == "rx" && !pulse.high_or_low)
+            })
+            .unwrap();
+        assert_eq!(first_low_rx, presses);
+
+        assert_eq!(
+            part2(&registry, &state, &mmap, &connections, 4),
+            Err(CycleError::NoCycle {
+                module: "lk",
+                presses: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_structure_errors() {
+        let registry = Registry::default();
+        let check = |input| {
+            let (mmap, _) = circuit(input);
+            let connections = to_connections(&mmap);
+            find_feeder(&registry, &mmap, &connections).map(|_| ())
+        };
+        assert_eq!(
+            check("broadcaster -> a\n%a -> b\n&b -> a\n"),
+            Err(CycleError::MissingRx)
+        );
+        assert_eq!(
+            check("broadcaster -> a\n%a -> rx\n"),
+            Err(CycleError::FeederNotConjunction("a"))
+        );
+        assert!(matches!(
+            check("broadcaster -> a, b\n%a -> rx\n%b -> rx\n"),
+            Err(CycleError::MultipleFeeders(_))
+        ));
+    }
+}
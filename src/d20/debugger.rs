@@ -0,0 +1,253 @@
+use super::*;
+
+use std::fmt::Write;
+use std::io::BufRead;
+
+const HELP: &str = "\
+press [n]                           finish the current press, or press the button n times
+step                                deliver one pulse
+state <module>                      show a module's state
+watch <module>                      show every pulse to or from module while pressing
+break when <module> sends low|high  stop as soon as module sends a pulse like that
+reset                               go back to the initial state
+quit                                leave the debugger
+";
+
+/// Steps through a circuit one pulse at a time. Commands are run with execute, which returns what
+/// to show the user
+pub struct Debugger<'a, 'b> {
+    connections: &'b Connections<'a, 'b>,
+    initial: AllState<'a>,
+    state: AllState<'a>,
+    /// Pulses sent but not delivered yet. Empty between presses
+    queue: VecDeque<Pulse<'a>>,
+    presses: usize,
+    watches: Vec<String>,
+    /// (module, level) to stop at
+    breakpoints: Vec<(String, bool)>,
+}
+
+fn level(high_or_low: bool) -> &'static str {
+    if high_or_low {
+        "high"
+    } else {
+        "low"
+    }
+}
+
+impl<'a, 'b> Debugger<'a, 'b> {
+    pub fn new(state: AllState<'a>, connections: &'b Connections<'a, 'b>) -> Self {
+        Debugger {
+            connections,
+            initial: state.clone(),
+            state,
+            queue: VecDeque::new(),
+            presses: 0,
+            watches: vec![],
+            breakpoints: vec![],
+        }
+    }
+
+    /// Run one command line
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => Ok(String::new()),
+            ["help"] => Ok(HELP.to_owned()),
+            ["press"] => Ok(self.press(1)),
+            ["press", n] => Ok(self.press(
+                n.parse()
+                    .map_err(|_| format!("expected a number of presses but got {}", n))?,
+            )),
+            ["step"] => {
+                let (pulse, hit_break) = self.deliver();
+                let mut out = format!("{}\n", self.describe(&pulse));
+                if hit_break {
+                    out += &self.describe_break(&pulse);
+                }
+                Ok(out)
+            }
+            ["state", module] => {
+                let module_state = self
+                    .state
+                    .get(module)
+                    .ok_or_else(|| format!("no module {}", module))?;
+                let json = module_state
+                    .to_json()
+                    .map_err(|err| format!("can't show {}: {}", module, err))?;
+                Ok(format!("{} {}\n", module, json))
+            }
+            ["watch", module] => {
+                self.watches.push(module.to_owned());
+                Ok(format!("watching {}\n", module))
+            }
+            ["break", "when", module, "sends", level @ ("low" | "high")] => {
+                self.breakpoints.push((module.to_owned(), level == "high"));
+                Ok(format!("breaking when {} sends {}\n", module, level))
+            }
+            ["reset"] => {
+                self.state = self.initial.clone();
+                self.queue.clear();
+                self.presses = 0;
+                Ok("reset to the initial state\n".to_owned())
+            }
+            _ => Err(format!("unknown command {:?}, try help", line.trim())),
+        }
+    }
+
+    /// Deliver the next pulse, pressing the button first if the last press is done. Returns the
+    /// pulse and whether it hit a breakpoint
+    fn deliver(&mut self) -> (Pulse<'a>, bool) {
+        if self.queue.is_empty() {
+            self.presses += 1;
+            self.queue
+                .extend(press_pulses(&mut self.state, self.connections));
+        }
+        let pulse = self.queue.pop_front().unwrap();
+        let new_pulses = update_state(&mut self.state, self.connections, &pulse);
+        self.queue.extend(new_pulses);
+        let hit_break = self
+            .breakpoints
+            .iter()
+            .any(|(module, high_or_low)| pulse.src == module && pulse.high_or_low == *high_or_low);
+        (pulse, hit_break)
+    }
+
+    /// Deliver pulses until n presses have finished (counting the one in progress, if any), showing
+    /// the watched ones. Stops early at a breakpoint
+    fn press(&mut self, n: usize) -> String {
+        let mut out = String::new();
+        for _ in 0..n {
+            loop {
+                let (pulse, hit_break) = self.deliver();
+                if self
+                    .watches
+                    .iter()
+                    .any(|module| pulse.src == module || pulse.dst == module)
+                {
+                    writeln!(out, "{}", self.describe(&pulse)).unwrap();
+                }
+                if hit_break {
+                    out += &self.describe_break(&pulse);
+                    return out;
+                }
+                if self.queue.is_empty() {
+                    break;
+                }
+            }
+        }
+        writeln!(out, "pressed {} times", self.presses).unwrap();
+        out
+    }
+
+    /// E.g. press 1: a -high-> inv
+    fn describe(&self, pulse: &Pulse<'_>) -> String {
+        format!(
+            "press {}: {} -{}-> {}",
+            self.presses,
+            pulse.src,
+            level(pulse.high_or_low),
+            pulse.dst
+        )
+    }
+
+    fn describe_break(&self, pulse: &Pulse<'_>) -> String {
+        format!(
+            "break: {} sent {} during press {} ({} pulses still to deliver)\n",
+            pulse.src,
+            level(pulse.high_or_low),
+            self.presses,
+            self.queue.len()
+        )
+    }
+}
+
+/// Run the debugger on stdin until quit or the end of input
+pub fn repl(debugger: &mut Debugger<'_, '_>) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    print!("> ");
+    io::Write::flush(&mut stdout)?;
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if matches!(line.trim(), "quit" | "exit") {
+            break;
+        }
+        match debugger.execute(&line) {
+            Ok(out) => print!("{}", out),
+            Err(err) => println!("error: {}", err),
+        }
+        print!("> ");
+        io::Write::flush(&mut stdout)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_debugger() {
+        let input = indoc! {"
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> output
+        "};
+        let (mmap, state) = circuit(input);
+        let connections = to_connections(&mmap);
+        let mut debugger = Debugger::new(state, &connections);
+        let mut run = |line| debugger.execute(line).unwrap();
+
+        assert_eq!(run("step"), "press 1: button -low-> broadcaster\n");
+        assert_eq!(run("state a"), "a {\"on\":false}\n");
+        // a flips as soon as the pulse is delivered
+        assert_eq!(run("step"), "press 1: broadcaster -low-> a\n");
+        assert_eq!(run("state a"), "a {\"on\":true}\n");
+        assert_eq!(run("step"), "press 1: a -high-> inv\n");
+
+        // finishes press 1, then does press 2
+        assert_eq!(run("watch b"), "watching b\n");
+        assert_eq!(
+            run("press 2"),
+            indoc! {"
+                press 1: inv -low-> b
+                press 1: b -high-> con
+                press 2: inv -high-> b
+                pressed 2 times
+            "}
+        );
+
+        assert_eq!(
+            run("break when con sends low"),
+            "breaking when con sends low\n"
+        );
+        assert_eq!(
+            run("press 5"),
+            indoc! {"
+                press 3: inv -low-> b
+                break: con sent low during press 3 (1 pulses still to deliver)
+            "}
+        );
+
+        assert_eq!(run("reset"), "reset to the initial state\n");
+        assert_eq!(run("state b"), "b {\"on\":false}\n");
+        assert_eq!(run("step"), "press 1: button -low-> broadcaster\n");
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let (mmap, state) = circuit("broadcaster -> a\n%a -> b\n");
+        let connections = to_connections(&mmap);
+        let mut debugger = Debugger::new(state, &connections);
+
+        assert!(debugger.execute("state nope").is_err());
+        assert!(debugger.execute("press lots").is_err());
+        assert!(debugger.execute("break when a sends medium").is_err());
+        assert!(debugger.execute("fly").is_err());
+        assert_eq!(debugger.execute("").unwrap(), "");
+    }
+}
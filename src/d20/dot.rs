@@ -0,0 +1,182 @@
+use super::*;
+
+use module::Kind;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// The graphviz shape for a module of this kind
+fn shape(kind: Option<Kind>) -> &'static str {
+    match kind {
+        Some(Kind::Broadcaster) => "doubleoctagon",
+        Some(Kind::FlipFlop) => "box",
+        Some(Kind::Conjunction) => "invhouse",
+        None => "ellipse",
+    }
+}
+
+/// Render the module network as a graphviz DOT graph. Flip-flops, conjunctions and the broadcaster
+/// get their own shapes, and destinations that aren't modules (like rx) are sinks. If state is
+/// given, modules are filled in by the pulse they'd send now (red for high, blue for low). If rx is
+/// fed by a conjunction, each of the sub-counters feeding that conjunction gets its own cluster.
+/// Module kinds are looked up in registry
+pub fn to_dot(
+    registry: &Registry<'_>,
+    mmap: &ModuleMap<'_>,
+    state: Option<&AllState<'_>>,
+) -> String {
+    let names: BTreeSet<&str> = mmap.keys().copied().collect();
+    let sinks: BTreeSet<&str> = mmap
+        .values()
+        .flat_map(|(_, dsts)| dsts.iter().copied())
+        .filter(|dst| !mmap.contains_key(dst))
+        .collect();
+
+    let node = |name: &str| {
+        let (prefix, _) = &mmap[name];
+        let mut attrs = vec![format!("shape={}", shape(registry.kind(prefix)))];
+        match state.and_then(|state| state.get(name)?.output()) {
+            Some(true) => attrs.push("style=filled, fillcolor=lightcoral".to_owned()),
+            Some(false) => attrs.push("style=filled, fillcolor=lightblue".to_owned()),
+            None => (),
+        }
+        format!("\"{}\" [{}];", name, attrs.join(", "))
+    };
+
+    let clusters = sub_counters(registry, mmap);
+    let clustered: HashSet<&str> = clusters
+        .iter()
+        .flat_map(|(_, members)| members.iter().copied())
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "digraph modules {{").unwrap();
+    for (idx, (input, members)) in clusters.iter().enumerate() {
+        writeln!(out, "    subgraph cluster_{} {{", idx).unwrap();
+        writeln!(out, "        label=\"{}\";", input).unwrap();
+        for name in members {
+            writeln!(out, "        {}", node(name)).unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+    for &name in names.iter().filter(|name| !clustered.contains(*name)) {
+        writeln!(out, "    {}", node(name)).unwrap();
+    }
+    for sink in &sinks {
+        writeln!(out, "    \"{}\" [shape=doublecircle];", sink).unwrap();
+    }
+    for &name in &names {
+        for dst in &mmap[name].1 {
+            writeln!(out, "    \"{}\" -> \"{}\";", name, dst).unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// For each input of the conjunction feeding rx, the modules that only lead to that input (not
+/// counting the broadcaster). Empty if rx isn't set up like that
+fn sub_counters<'a>(
+    registry: &Registry<'_>,
+    mmap: &ModuleMap<'a>,
+) -> Vec<(ModuleIdent<'a>, BTreeSet<ModuleIdent<'a>>)> {
+    let connections = to_connections(mmap);
+    let Ok((_, inputs)) = cycles::find_feeder(registry, mmap, &connections) else {
+        return vec![];
+    };
+    let rev_connections = reverse_map(&connections);
+
+    let upstream: Vec<BTreeSet<ModuleIdent>> = inputs
+        .iter()
+        .map(|&input| {
+            let mut seen = BTreeSet::new();
+            let mut stack = vec![input];
+            while let Some(name) = stack.pop() {
+                if name != "broadcaster" && seen.insert(name) {
+                    stack.extend(rev_connections.get(name).into_iter().flatten());
+                }
+            }
+            seen
+        })
+        .collect();
+
+    // modules upstream of more than one input are shared, so they don't go in any cluster
+    let mut counts: HashMap<ModuleIdent, usize> = HashMap::new();
+    for name in upstream.iter().flatten() {
+        *counts.entry(name).or_default() += 1;
+    }
+    inputs
+        .into_iter()
+        .zip(upstream)
+        .map(|(input, members)| {
+            let members = members
+                .into_iter()
+                .filter(|name| counts[name] == 1)
+                .collect();
+            (input, members)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_to_dot() {
+        let input = indoc! {"
+            broadcaster -> a, b
+            %a -> qr
+            %b -> lk
+            &qr -> hub
+            &lk -> hub
+            &hub -> rx
+        "};
+        let registry = Registry::default();
+        let (mmap, mut state) = circuit(input);
+
+        assert_eq!(
+            to_dot(&registry, &mmap, None),
+            indoc! {r#"
+                digraph modules {
+                    subgraph cluster_0 {
+                        label="lk";
+                        "b" [shape=box];
+                        "lk" [shape=invhouse];
+                    }
+                    subgraph cluster_1 {
+                        label="qr";
+                        "a" [shape=box];
+                        "qr" [shape=invhouse];
+                    }
+                    "broadcaster" [shape=doubleoctagon];
+                    "hub" [shape=invhouse];
+                    "rx" [shape=doublecircle];
+                    "a" -> "qr";
+                    "b" -> "lk";
+                    "broadcaster" -> "a";
+                    "broadcaster" -> "b";
+                    "hub" -> "rx";
+                    "lk" -> "hub";
+                    "qr" -> "hub";
+                }
+            "#}
+        );
+
+        // after one press a and b are on, so qr and lk send low, so hub sends high
+        push_button(&mut state, &to_connections(&mmap)).for_each(drop);
+        let dot = to_dot(&registry, &mmap, Some(&state));
+        assert!(dot.contains(r#""a" [shape=box, style=filled, fillcolor=lightcoral];"#));
+        assert!(dot.contains(r#""qr" [shape=invhouse, style=filled, fillcolor=lightblue];"#));
+        assert!(dot.contains(r#""hub" [shape=invhouse, style=filled, fillcolor=lightcoral];"#));
+        assert!(dot.contains(r#""broadcaster" [shape=doubleoctagon];"#));
+    }
+
+    #[test]
+    fn test_no_clusters() {
+        let registry = Registry::default();
+        let (mmap, _) = circuit("broadcaster -> a\n%a -> output\n");
+        assert_eq!(sub_counters(&registry, &mmap), vec![]);
+        assert!(!to_dot(&registry, &mmap, None).contains("subgraph"));
+    }
+}
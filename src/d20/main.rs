@@ -1,17 +1,33 @@
-use itertools::Itertools;
-use parse::{parse, ModuleType};
-use serde::Serialize;
+use compiled::CompiledCircuit;
+use module::{Module, Registry, Sends};
+#[cfg(test)]
+use parse::parse;
+use parse::{parse_lines, to_module_map, ModuleMap};
+use snapshot::Snapshot;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::error::Error;
 use std::fs;
+use std::io;
+use std::time::Instant;
+mod compiled;
+mod cycles;
+mod debugger;
+mod dot;
+mod module;
 mod parse;
+mod snapshot;
+mod stats;
+mod trace;
+mod validate;
 //
-// AllState = Vec<ModuleState>
+// AllState = HashMap<ModuleIdent, Box<dyn Module>>
 //
-// ModuleState
-//      FlipFlop(bool) // on or off
-//      Conjunction(Vec<bool>) //TODO: make smallvec
+// Module (trait, see module.rs)
+//      Broadcaster
+//      FlipFlop // on or off
+//      Conjunction // most recent pulse from each input
+//      ... anything else added to the Registry
 //
 // ModuleIdent = &str
 //
@@ -21,13 +37,8 @@ mod parse;
 // PulseQueue:
 //      Vec<(ModuleIdent, ModuleIdent, bool)
 //
-// Parse::ModuleType
-//      Broadcaster
-//      FlipFlop
-//      Conjunction
-//
-// Parse::ModuleMap // map from module ident -> (type for module, list of connections)
-//      HashMap<&str, (ParseType, Vec<&str>)>
+// Parse::ModuleMap // map from module ident -> (prefix for module type, list of connections)
+//      HashMap<&str, (&str, Vec<&str>)>
 //
 // fn parse -> Connections
 //   ...
@@ -52,36 +63,206 @@ mod parse;
 //              put new pulses on the queue
 //              yield pulses // TODO time to use generators for the first time??? If not just append to a vec
 
+/// How many times part 2 presses the button looking for the cycles that lead to rx
+const MAX_CYCLE_PRESSES: usize = 100_000;
+
 type ModuleIdent<'a> = &'a str;
 type Connections<'a, 'b> = HashMap<ModuleIdent<'a>, &'b Vec<ModuleIdent<'a>>>;
 
-#[derive(Debug, Clone, Serialize)]
-enum ModuleStateVal<'a> {
-    Broadcaster,
-    FlipFlop(bool),
-    Conjunction(Vec<(ModuleIdent<'a>, bool)>),
-}
-
-type AllState<'a> = HashMap<ModuleIdent<'a>, ModuleStateVal<'a>>;
+type AllState<'a> = HashMap<ModuleIdent<'a>, Box<dyn Module<'a> + 'a>>;
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use indoc::indoc;
+    use module::StateError;
 
     #[test]
     fn test_to_json() {
-        let mut all_state = HashMap::new();
-
-        // Example data
-        all_state.insert("module1".to_string(), ModuleStateVal::Broadcaster);
-        all_state.insert("module2".to_string(), ModuleStateVal::FlipFlop(true));
-        all_state.insert(
-            "module3".to_string(),
-            ModuleStateVal::Conjunction(vec![("module4", false)]),
+        let (mmap, mut all_state) = circuit("broadcaster -> a\n%a -> inv\n&inv -> a\n");
+        let connections = to_connections(&mmap);
+        push_button(&mut all_state, &connections).for_each(drop);
+
+        let serialized: HashMap<_, _> = all_state
+            .iter()
+            .map(|(name, module)| (*name, module.to_json().unwrap()))
+            .collect();
+        assert_eq!(
+            serde_json::to_value(serialized).unwrap(),
+            serde_json::json!({
+                "broadcaster": null,
+                "a": {"on": false},
+                "inv": {"recent_pulses": [["a", false]]},
+            })
+        );
+    }
+
+    /// Sends high only when its inputs disagree
+    #[derive(Debug, Clone, Default)]
+    struct Xor<'a> {
+        recent_pulses: Vec<(ModuleIdent<'a>, bool)>,
+    }
+
+    impl<'a> Module<'a> for Xor<'a> {
+        fn connect_input(&mut self, src: ModuleIdent<'a>) {
+            self.recent_pulses.push((src, false));
+        }
+
+        fn receive(&mut self, src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+            let (_, val) = self
+                .recent_pulses
+                .iter_mut()
+                .find(|(ident, _)| *ident == src)
+                .unwrap();
+            *val = high_or_low;
+            let high = self.recent_pulses.iter().filter(|(_, val)| *val).count() % 2 == 1;
+            vec![(None, high)]
+        }
+
+        fn to_json(&self) -> Result<serde_json::Value, StateError> {
+            Ok(serde_json::json!(self.recent_pulses))
+        }
+
+        fn load_json(&mut self, json: serde_json::Value) -> Result<(), StateError> {
+            module::load_recent_pulses(&mut self.recent_pulses, serde_json::from_value(json)?)
+        }
+
+        fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+            Box::new(self.clone())
+        }
+    }
+
+    /// Holds on to the pulses it receives, and sends them all on the next button press
+    #[derive(Debug, Clone, Default)]
+    struct Delay {
+        pending: Vec<bool>,
+    }
+
+    impl<'a> Module<'a> for Delay {
+        fn receive(&mut self, _src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+            self.pending.push(high_or_low);
+            vec![]
+        }
+
+        fn on_press(&mut self) -> Sends {
+            self.pending.drain(..).map(|pulse| (None, pulse)).collect()
+        }
+
+        fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+            Box::new(self.clone())
+        }
+    }
+
+    /// Sends each pulse to its first output, and the opposite pulse to its second
+    #[derive(Debug, Clone, Default)]
+    struct Splitter;
+
+    impl<'a> Module<'a> for Splitter {
+        fn receive(&mut self, _src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+            vec![(Some(0), high_or_low), (Some(1), !high_or_low)]
+        }
+
+        fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_delay_and_splitter() {
+        let mut registry = Registry::default();
+        registry
+            .register("~", None, || Box::<Delay>::default())
+            .register("/", None, || Box::new(Splitter));
+        let (_, mmap) = parse(&registry, "broadcaster -> d\n~d -> s\n/s -> hi, lo\n").unwrap();
+        let connections = to_connections(&mmap);
+        let mut all_state = init_state(&registry, &mmap, &connections);
+        let mut pulses = || {
+            push_button(&mut all_state, &connections)
+                .map(|pulse| (pulse.src, pulse.dst, pulse.high_or_low))
+                .collect::<Vec<_>>()
+        };
+
+        // d holds on to the first press's pulse
+        assert_eq!(
+            pulses(),
+            vec![
+                ("button", "broadcaster", false),
+                ("broadcaster", "d", false)
+            ]
+        );
+        // and sends it on the next press, right after the button, for s to split
+        assert_eq!(
+            pulses(),
+            vec![
+                ("button", "broadcaster", false),
+                ("d", "s", false),
+                ("broadcaster", "d", false),
+                ("s", "hi", false),
+                ("s", "lo", true),
+            ]
         );
+    }
 
-        let serialized = serde_json::to_string(&all_state).unwrap();
-        println!("{}", serialized)
+    #[test]
+    fn test_unprefixed_names() {
+        let registry = Registry::default();
+        assert!(parse(&registry, "broadcaster -> a\n%a -> b\n").is_ok());
+        assert!(parse(&registry, "broadcaster -> a\na -> b\n").is_err());
+        assert!(parse(&registry, "start -> a\n%a -> b\n").is_err());
+        // broadcaster with a prefix is just an ordinary module of that type
+        assert!(parse(&registry, "%broadcaster -> a\n").is_ok());
+    }
+
+    #[test]
+    fn test_custom_module() {
+        let input = indoc! {"
+            broadcaster -> a, b
+            %a -> x
+            %b -> c
+            %c -> x
+            ^x -> out
+        "};
+        assert!(parse(&Registry::default(), input).is_err());
+
+        let mut registry = Registry::default();
+        registry.register("^", None, || Box::<Xor>::default());
+        let (_, mmap) = parse(&registry, input).unwrap();
+        let connections = to_connections(&mmap);
+        let mut all_state = init_state(&registry, &mmap, &connections);
+
+        // a flips every press and c every other press. x sends a pulse each time either changes,
+        // and it's high whenever exactly one of them is on
+        let xor_outputs: Vec<Vec<bool>> = (0..4)
+            .map(|_| {
+                push_button(&mut all_state, &connections)
+                    .filter(|pulse| pulse.src == "x")
+                    .map(|pulse| pulse.high_or_low)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            xor_outputs,
+            vec![
+                vec![true],
+                vec![false, true],
+                vec![false],
+                vec![true, false]
+            ]
+        );
+
+        // x can be saved and restored like the built-in modules
+        push_button(&mut all_state, &connections).for_each(drop);
+        let snapshot = Snapshot::capture(&all_state, 5).unwrap();
+        let mut resumed = init_state(&registry, &mmap, &connections);
+        assert_eq!(snapshot.restore(&mut resumed).unwrap(), 5);
+        for _ in 0..4 {
+            let pulses = |state| {
+                push_button(state, &connections)
+                    .map(|pulse| (pulse.src, pulse.dst, pulse.high_or_low))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(pulses(&mut all_state), pulses(&mut resumed));
+        }
     }
 }
 
@@ -106,6 +287,63 @@ where
     h
 }
 
+/// Where each module in mmap sends its pulses
+fn to_connections<'a, 'b>(mmap: &'b ModuleMap<'a>) -> Connections<'a, 'b> {
+    mmap.iter().map(|(src, (_, dst))| (*src, dst)).collect()
+}
+
+/// Parse input with the built-in module kinds, and make every module in its initial state
+#[cfg(test)]
+fn circuit(input: &str) -> (ModuleMap<'_>, AllState<'_>) {
+    let registry = Registry::default();
+    let (_, mmap) = parse(&registry, input).unwrap();
+    let state = init_state(&registry, &mmap, &to_connections(&mmap));
+    (mmap, state)
+}
+
+/// Make every module in its initial state, and tell each one about its inputs
+fn init_state<'a>(
+    registry: &Registry<'a>,
+    mmap: &ModuleMap<'a>,
+    connections: &Connections<'a, '_>,
+) -> AllState<'a> {
+    let mut state: AllState<'a> = mmap
+        .iter()
+        .map(|(src, (prefix, _))| (*src, registry.construct(prefix).unwrap()))
+        .collect();
+    for (dst, srcs) in reverse_map(connections) {
+        if let Some(module) = state.get_mut(dst) {
+            for src in srcs {
+                module.connect_input(src);
+            }
+        }
+    }
+    state
+}
+
+/// The pulses a module sends, addressed to its downstream modules
+fn fan_out<'a, 'b>(
+    src: ModuleIdent<'a>,
+    downstream_modules: &'b [ModuleIdent<'a>],
+    sends: Sends,
+) -> impl Iterator<Item = Pulse<'a>> + 'b
+where
+    'a: 'b,
+{
+    sends.into_iter().flat_map(move |(output, high_or_low)| {
+        // an output the module doesn't have gets nothing
+        let dsts = match output {
+            None => downstream_modules,
+            Some(i) => downstream_modules.get(i..=i).unwrap_or(&[]),
+        };
+        dsts.iter().map(move |dst| Pulse {
+            src,
+            dst,
+            high_or_low,
+        })
+    })
+}
+
 // TODO: make this an iterator
 fn update_state<'a, 'b>(
     state: &'b mut AllState<'a>,
@@ -120,59 +358,38 @@ fn update_state<'a, 'b>(
     let emptyvec = vec![];
     let downstream_modules = *connections.get(dst).unwrap_or(&&emptyvec);
 
-    let module_state = match state.get_mut(dst) {
+    let module = match state.get_mut(dst) {
         None => {
             // eprintln!("Warning: got pulse to blackhole dst {}", dst);
             return vec![];
         }
         Some(x) => x,
     };
-    let new_pulses: Vec<_> = match module_state {
-        ModuleStateVal::Broadcaster => {
-            debug_assert!(dst == &"broadcaster");
-            downstream_modules
-                .iter()
-                .map(|downstream| Pulse {
-                    src: dst,
-                    dst: downstream,
-                    high_or_low: *high_or_low,
-                })
-                .collect()
-        }
-        ModuleStateVal::FlipFlop(ref mut flip_flop_state) => match high_or_low {
-            true => vec![],
-            false => {
-                *flip_flop_state = !*flip_flop_state;
-                downstream_modules
-                    .iter()
-                    .map(|ds_mod| Pulse {
-                        src: dst,
-                        dst: ds_mod,
-                        high_or_low: *flip_flop_state,
-                    })
-                    .collect()
-            }
-        },
+    fan_out(dst, downstream_modules, module.receive(src, *high_or_low)).collect()
+}
 
-        ModuleStateVal::Conjunction(ref mut recent_pulses) => {
-            let (_, val) = recent_pulses
-                .iter_mut()
-                .find(|(ident, _)| ident == src)
-                .unwrap();
-            *val = *high_or_low;
-
-            let new_pulses_val = !recent_pulses.iter().all(|(_, val)| *val);
-            downstream_modules
-                .iter()
-                .map(|module| Pulse {
-                    src: dst,
-                    dst: module,
-                    high_or_low: new_pulses_val,
-                })
-                .collect()
-        }
-    };
-    new_pulses
+/// The pulses that start a button press: the button's low pulse to broadcaster, then whatever each
+/// module sends when the button is pressed, in order of module name
+fn press_pulses<'a>(state: &mut AllState<'a>, connections: &Connections<'a, '_>) -> Vec<Pulse<'a>> {
+    let mut pressed: Vec<_> = state
+        .iter_mut()
+        .map(|(&name, module)| (name, module.on_press()))
+        .filter(|(_, sends)| !sends.is_empty())
+        .collect();
+    pressed.sort_by_key(|&(name, _)| name);
+
+    let mut pulses = vec![Pulse {
+        src: "button",
+        dst: "broadcaster",
+        high_or_low: false,
+    }];
+    for (name, sends) in pressed {
+        let downstream_modules = connections
+            .get(name)
+            .map_or(&[][..], |dsts| dsts.as_slice());
+        pulses.extend(fan_out(name, downstream_modules, sends));
+    }
+    pulses
 }
 
 fn push_button<'a, 'b>(
@@ -182,70 +399,191 @@ fn push_button<'a, 'b>(
 where
     'a: 'b,
 {
-    // TODO: might have to send a button pulse
-    let mut queue = VecDeque::new();
-
-    // init the initial pulses
-    // let broadcaster_connetions = connections.get("broadcaster").unwrap();
-    // queue.extend(broadcaster_connetions.iter().map(|&dst| Pulse {
-    //     src: "broadcaster",
-    //     dst,
-    //     high_or_low: false,
-    // }));
-
-    queue.push_front(Pulse {
-        src: "button",
-        dst: "broadcaster",
-        high_or_low: false,
-    });
+    let mut queue: VecDeque<_> = press_pulses(state, connections).into();
 
     std::iter::from_fn(move || {
         let pulse = queue.pop_front()?;
-        let new_pulses = update_state(state, &connections, &pulse);
-        queue.extend(new_pulses.into_iter());
+        let new_pulses = update_state(state, connections, &pulse);
+        queue.extend(new_pulses);
         Some(pulse)
     })
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let content = fs::read_to_string("src/d20/input")?;
-    let (_, mmap) = parse(&content).unwrap();
-    // println!("Parsed val {:#?}", mmap);
-    let connections: Connections<'_, '_> = mmap.iter().map(|(src, (_, dst))| (*src, dst)).collect();
-    let rev_connections = reverse_map(&connections);
-    let state: AllState<'_> = mmap
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // --replay file query...: answer a query about a trace without touching the input
+    if let Some(pos) = args.iter().position(|arg| arg == "--replay") {
+        let path = args.get(pos + 1).ok_or("--replay needs a trace file")?;
+        print!(
+            "{}",
+            trace::replay(&fs::read_to_string(path)?, &args[pos + 2..])?
+        );
+        return Ok(());
+    }
+
+    // --debug [file]: step through file (or the input) in the debugger
+    let debug = args.iter().position(|arg| arg == "--debug");
+    let path = debug
+        .and_then(|pos| args.get(pos + 1))
+        .filter(|arg| !arg.starts_with("--"))
+        .map_or("src/d20/input", String::as_str);
+    let content = fs::read_to_string(path)?;
+    let registry = Registry::default();
+    let (_, lines) = parse_lines(&registry, &content).map_err(|err| err.to_string())?;
+    let issues = validate::validate(&registry, &lines);
+    for issue in &issues {
+        eprintln!("{}: {}", issue.severity(), issue);
+    }
+    if issues
         .iter()
-        .map(|(src, (module_type, _))| {
-            let key = src;
-            let val = match *module_type {
-                ModuleType::Broadcaster => ModuleStateVal::Broadcaster,
-                ModuleType::FlipFlop => ModuleStateVal::FlipFlop(false),
-                ModuleType::Conjunction => ModuleStateVal::Conjunction(
-                    rev_connections
-                        .get(src)
-                        .unwrap()
-                        .iter()
-                        .map(|&prev| (prev, false))
-                        .collect(),
-                ),
-            };
-            (*key, val)
-        })
-        .collect();
+        .any(|issue| issue.severity() == validate::Severity::Error)
+    {
+        return Err("the module map has errors".into());
+    }
+    let mmap = to_module_map(lines);
+    // println!("Parsed val {:#?}", mmap);
+    let connections = to_connections(&mmap);
+    let mut state = init_state(&registry, &mmap, &connections);
+
+    // --resume file: carry on from a snapshot instead of the initial state
+    let mut presses = 0;
+    if let Some(pos) = args.iter().position(|arg| arg == "--resume") {
+        let path = args.get(pos + 1).ok_or("--resume needs a snapshot file")?;
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)?;
+        presses = snapshot.restore(&mut state)?;
+    }
+
+    if debug.is_some() {
+        let mut debugger = debugger::Debugger::new(state, &connections);
+        debugger::repl(&mut debugger)?;
+        return Ok(());
+    }
+
+    // --dot: print the module network as graphviz, coloured by state if it was resumed
+    if args.iter().any(|arg| arg == "--dot") {
+        let resumed = args.iter().any(|arg| arg == "--resume");
+        print!(
+            "{}",
+            dot::to_dot(&registry, &mmap, resumed.then_some(&state))
+        );
+        return Ok(());
+    }
+
+    // --trace n [file]: press the button n more times, writing every pulse to file (or stdout)
+    if let Some(pos) = args.iter().position(|arg| arg == "--trace") {
+        let n: usize = args
+            .get(pos + 1)
+            .ok_or("--trace needs a number of presses")?
+            .parse()?;
+        match args.get(pos + 2).filter(|arg| !arg.starts_with("--")) {
+            Some(path) => trace::record(
+                &mut state,
+                &connections,
+                presses + 1,
+                n,
+                io::BufWriter::new(fs::File::create(path)?),
+            )?,
+            None => trace::record(
+                &mut state,
+                &connections,
+                presses + 1,
+                n,
+                io::stdout().lock(),
+            )?,
+        }
+        return Ok(());
+    }
+
+    // --dump n [file]: press the button n more times, then write a snapshot to file (or stdout)
+    if let Some(pos) = args.iter().position(|arg| arg == "--dump") {
+        let n: usize = args
+            .get(pos + 1)
+            .ok_or("--dump needs a number of presses")?
+            .parse()?;
+        for _ in 0..n {
+            push_button(&mut state, &connections).for_each(drop);
+        }
+        let snapshot = serde_json::to_string(&Snapshot::capture(&state, presses + n)?)?;
+        match args.get(pos + 2).filter(|arg| !arg.starts_with("--")) {
+            Some(path) => fs::write(path, snapshot + "\n")?,
+            None => println!("{}", snapshot),
+        }
+        return Ok(());
+    }
+
+    // --stats n [csv|json]: per module and per edge pulse counts over n more presses
+    if let Some(pos) = args.iter().position(|arg| arg == "--stats") {
+        let n: usize = args
+            .get(pos + 1)
+            .ok_or("--stats needs a number of presses")?
+            .parse()?;
+        let report = stats::collect(&registry, state, &mmap, &connections, n);
+        match args.get(pos + 2).map(String::as_str) {
+            Some("json") => println!("{}", serde_json::to_string_pretty(&report)?),
+            Some("csv") | None => print!("{}", report.to_csv()),
+            Some(format) => return Err(format!("unknown stats format {}", format).into()),
+        }
+        return Ok(());
+    }
+
+    // --bench n: time n presses of the compiled simulator from the initial state. On an input
+    // shaped like the puzzle's (four 12 bit counters, about 60 pulses a press) a release build
+    // does about 2 million presses (130 million pulses) a second
+    if let Some(pos) = args.iter().position(|arg| arg == "--bench") {
+        let n: usize = args
+            .get(pos + 1)
+            .ok_or("--bench needs a number of presses")?
+            .parse()?;
+        let mut circuit =
+            CompiledCircuit::compile(&registry, &mmap).map_err(|err| err.to_string())?;
+        let start = Instant::now();
+        let pulses: usize = (0..n)
+            .map(|_| {
+                let (low, high) = circuit.press();
+                low + high
+            })
+            .sum();
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "{} presses, {} pulses in {:.3}s ({:.0} presses/s)",
+            n,
+            pulses,
+            elapsed,
+            n as f64 / elapsed
+        );
+        return Ok(());
+    }
 
     // part 1
     {
         let (mut low_pulses, mut high_pulses): (usize, usize) = (0, 0);
-        let mut p1state = state.clone();
-        // TODO: Investigate why I have to do this collect(). Something about can't allow captured
-        // variables to escpae a closure
-        for pulse in
-            (0..1000).flat_map(|_| push_button(&mut p1state, &connections).collect::<Vec<_>>())
-        {
-            if pulse.high_or_low {
-                high_pulses += 1;
-            } else {
-                low_pulses += 1;
+        // the compiled simulator only knows the initial state and the built-in module kinds, so
+        // stick to the slow one if resumed or if there's anything else
+        let compiled = match CompiledCircuit::compile(&registry, &mmap) {
+            Ok(circuit) if presses == 0 => Some(circuit),
+            Ok(_) => None,
+            Err(err) => {
+                eprintln!("warning: using the slow simulator because {}", err);
+                None
+            }
+        };
+        if let Some(mut circuit) = compiled {
+            for _ in 0..1000 {
+                let (low, high) = circuit.press();
+                low_pulses += low;
+                high_pulses += high;
+            }
+        } else {
+            let mut p1state = state.clone();
+            for _ in 0..1000 {
+                for pulse in push_button(&mut p1state, &connections) {
+                    if pulse.high_or_low {
+                        high_pulses += 1;
+                    } else {
+                        low_pulses += 1;
+                    }
+                }
             }
         }
         println!(
@@ -258,41 +596,13 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // part 2
     {
-        let mut button_presses: usize = 0;
-        let mut p2state = state.clone();
-        let intersting_nodes = ["qr", "lk", "lz", "ft"];
-        let mut interesting_nodes_periods: HashMap<_, usize> = HashMap::new();
-        'outer: loop {
-            button_presses += 1;
-            let activated_interesting_nodes = push_button(&mut p2state, &connections)
-                .filter_map(|pulse| {
-                    if intersting_nodes.contains(&pulse.src) && !pulse.high_or_low {
-                        return Some(pulse.src);
-                    }
-                    None
-                })
-                .sorted()
-                .dedup()
-                .collect::<Vec<_>>();
-            if activated_interesting_nodes.len() > 0 {
-                for node in activated_interesting_nodes {
-                    let x = interesting_nodes_periods.entry(node);
-                    x.or_insert(button_presses);
-                }
-            }
-
-            if interesting_nodes_periods.len() == 4 {
-                break 'outer;
-            }
-        }
-        println!("periods {:?}", &interesting_nodes_periods);
-        println!(
-            "p2 :: {}",
-            interesting_nodes_periods
-                .values()
-                .into_iter()
-                .product::<usize>()
-        );
+        // the error borrows from the input, so it can't be returned as is
+        let (cycles, cycle_presses) =
+            cycles::part2(&registry, &state, &mmap, &connections, MAX_CYCLE_PRESSES)
+                .map_err(|err| format!("part 2: {}", err))?;
+        // cycle detection starts counting from the resumed state
+        println!("cycles {:?}", cycles);
+        println!("p2 :: {}", presses + cycle_presses);
     }
 
     Ok(())
@@ -0,0 +1,278 @@
+use super::ModuleIdent;
+
+use serde::{de::Error as _, Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug};
+
+/// Why a module's state couldn't be saved or loaded
+#[derive(Debug)]
+pub enum StateError {
+    /// The module doesn't implement to_json and load_json
+    Unsupported,
+    /// The JSON doesn't fit the module
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Unsupported => write!(f, "module does not support snapshots"),
+            StateError::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl From<serde_json::Error> for StateError {
+    fn from(err: serde_json::Error) -> Self {
+        StateError::Json(err)
+    }
+}
+
+/// The pulses a module sends, in order. Each is sent to one of the module's outputs, by its index in
+/// the module's list of destinations, or to all of them if the index is None
+pub type Sends = Vec<(Option<usize>, bool)>;
+
+/// A module in the pulse network. Modules only see the pulses sent to them and the button being
+/// pressed; the simulator takes care of delivering whatever they send downstream
+pub trait Module<'a>: Debug {
+    /// Tell the module that src sends pulses to it. Called once per input before any pulses are sent
+    fn connect_input(&mut self, _src: ModuleIdent<'a>) {}
+
+    /// Handle a pulse from src, updating the module's state. Returns the pulses to send
+    fn receive(&mut self, src: ModuleIdent<'a>, high_or_low: bool) -> Sends;
+
+    /// Called every time the button is pressed, before any pulses are delivered. Returns pulses to
+    /// send, which go after the button's pulse to broadcaster
+    fn on_press(&mut self) -> Sends {
+        vec![]
+    }
+
+    /// The pulse the module would send if it sent one now, if that only depends on its state
+    fn output(&self) -> Option<bool> {
+        None
+    }
+
+    /// The module's state as JSON. Modules that don't need to be saved in snapshots can leave this
+    /// and load_json out
+    fn to_json(&self) -> Result<serde_json::Value, StateError> {
+        Err(StateError::Unsupported)
+    }
+
+    /// Set the module's state from JSON made by to_json
+    fn load_json(&mut self, _json: serde_json::Value) -> Result<(), StateError> {
+        Err(StateError::Unsupported)
+    }
+
+    fn box_clone(&self) -> Box<dyn Module<'a> + 'a>;
+}
+
+impl<'a> Clone for Box<dyn Module<'a> + 'a> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// Sends whatever it receives to every downstream module
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Broadcaster;
+
+impl<'a> Module<'a> for Broadcaster {
+    fn receive(&mut self, _src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+        vec![(None, high_or_low)]
+    }
+
+    fn to_json(&self) -> Result<serde_json::Value, StateError> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    fn load_json(&mut self, json: serde_json::Value) -> Result<(), StateError> {
+        *self = serde_json::from_value(json)?;
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+        Box::new(self.clone())
+    }
+}
+
+/// Ignores high pulses. A low pulse toggles it on or off, and it sends high when turning on and low
+/// when turning off
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlipFlop {
+    pub on: bool,
+}
+
+impl<'a> Module<'a> for FlipFlop {
+    fn receive(&mut self, _src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+        if high_or_low {
+            return vec![];
+        }
+        self.on = !self.on;
+        vec![(None, self.on)]
+    }
+
+    fn output(&self) -> Option<bool> {
+        Some(self.on)
+    }
+
+    fn to_json(&self) -> Result<serde_json::Value, StateError> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    fn load_json(&mut self, json: serde_json::Value) -> Result<(), StateError> {
+        *self = serde_json::from_value(json)?;
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+        Box::new(self.clone())
+    }
+}
+
+/// Remembers the most recent pulse from each input (starting at low). Sends low if they're all
+/// high, otherwise high
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Conjunction<'a> {
+    #[serde(borrow)]
+    pub recent_pulses: Vec<(ModuleIdent<'a>, bool)>,
+}
+
+impl<'a> Module<'a> for Conjunction<'a> {
+    fn connect_input(&mut self, src: ModuleIdent<'a>) {
+        self.recent_pulses.push((src, false));
+    }
+
+    fn receive(&mut self, src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+        let (_, val) = self
+            .recent_pulses
+            .iter_mut()
+            .find(|(ident, _)| *ident == src)
+            .unwrap();
+        *val = high_or_low;
+        self.output().map(|out| (None, out)).into_iter().collect()
+    }
+
+    fn output(&self) -> Option<bool> {
+        Some(!self.recent_pulses.iter().all(|(_, val)| *val))
+    }
+
+    fn to_json(&self) -> Result<serde_json::Value, StateError> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    fn load_json(&mut self, json: serde_json::Value) -> Result<(), StateError> {
+        #[derive(Deserialize)]
+        struct Saved {
+            recent_pulses: Vec<(String, bool)>,
+        }
+        let saved: Saved = serde_json::from_value(json)?;
+        load_recent_pulses(&mut self.recent_pulses, saved.recent_pulses)
+    }
+
+    fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+        Box::new(self.clone())
+    }
+}
+
+/// Set the most recent pulse from each input to the saved ones, for modules that remember their
+/// inputs like Conjunction. The input names borrow from the puzzle input, so they're matched up
+/// rather than replaced, and saved has to have exactly the same inputs
+pub fn load_recent_pulses<'a>(
+    recent_pulses: &mut [(ModuleIdent<'a>, bool)],
+    saved: Vec<(String, bool)>,
+) -> Result<(), StateError> {
+    if saved.len() != recent_pulses.len() {
+        return Err(serde_json::Error::custom(format!(
+            "expected {} inputs but got {}",
+            recent_pulses.len(),
+            saved.len()
+        ))
+        .into());
+    }
+    for (name, val) in saved {
+        let (_, recent) = recent_pulses
+            .iter_mut()
+            .find(|(ident, _)| *ident == name)
+            .ok_or_else(|| serde_json::Error::custom(format!("unknown input {}", name)))?;
+        *recent = val;
+    }
+    Ok(())
+}
+
+/// Makes a module in its initial state
+pub type Constructor<'a> = fn() -> Box<dyn Module<'a> + 'a>;
+
+fn construct<'a, M: Module<'a> + Default + 'a>() -> Box<dyn Module<'a> + 'a> {
+    Box::new(M::default())
+}
+
+/// How a built-in kind of module behaves, for the parts of the program that look at the circuit
+/// without simulating it (validation, cycle detection, stats, graphviz and the compiled simulator)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Sends whatever it receives
+    Broadcaster,
+    /// Toggles on low pulses and ignores high ones
+    FlipFlop,
+    /// Sends low once every input's most recent pulse was high
+    Conjunction,
+}
+
+/// The kinds of module an input can contain, keyed by the prefix in front of the module's name. E.g.
+/// % in %a -> b. The empty prefix is for broadcaster, which is the only module parse lets go
+/// without one
+#[derive(Debug, Clone)]
+pub struct Registry<'a> {
+    kinds: HashMap<&'static str, (Option<Kind>, Constructor<'a>)>,
+}
+
+impl<'a> Registry<'a> {
+    /// A registry with no module kinds
+    pub fn new() -> Self {
+        Registry {
+            kinds: HashMap::new(),
+        }
+    }
+
+    /// Add a module kind, replacing any kind that already has this prefix. kind is None for modules
+    /// that don't behave like any of the built-in ones: they can be simulated, but nothing else
+    /// assumes anything about them
+    pub fn register(
+        &mut self,
+        prefix: &'static str,
+        kind: Option<Kind>,
+        constructor: Constructor<'a>,
+    ) -> &mut Self {
+        self.kinds.insert(prefix, (kind, constructor));
+        self
+    }
+
+    pub fn contains(&self, prefix: &str) -> bool {
+        self.kinds.contains_key(prefix)
+    }
+
+    /// The built-in kind registered for prefix, if there is one
+    pub fn kind(&self, prefix: &str) -> Option<Kind> {
+        self.kinds.get(prefix).and_then(|(kind, _)| *kind)
+    }
+
+    /// Make a new module for prefix, if it's registered
+    pub fn construct(&self, prefix: &str) -> Option<Box<dyn Module<'a> + 'a>> {
+        self.kinds.get(prefix).map(|(_, constructor)| constructor())
+    }
+}
+
+impl<'a> Default for Registry<'a> {
+    /// The module kinds from the puzzle
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry
+            .register("", Some(Kind::Broadcaster), construct::<Broadcaster>)
+            .register("%", Some(Kind::FlipFlop), construct::<FlipFlop>)
+            .register("&", Some(Kind::Conjunction), construct::<Conjunction>);
+        registry
+    }
+}
@@ -1,32 +1,24 @@
+use super::module::Registry;
+
 use nom::{
-    branch::alt,
-    bytes::complete::tag,
+    bytes::complete::{tag, take_till},
     character::complete::{alpha1, newline, space0, space1},
-    combinator::{all_consuming, map},
+    combinator::{all_consuming, verify},
     multi::{many0, separated_list1},
     sequence::{terminated, tuple},
     IResult,
 };
 use std::collections::HashMap;
 
-// Parse::ModuleType
-//      Broadcaster
-//      FlipFlop
-//      Conjunction
-//
-// Parse::ModuleMap // map from module ident -> (type for module, list of connections)
-//      HashMap<&str, (ParseType, Vec<&str>)>
+// Parse::ModuleMap // map from module ident -> (prefix for module type, list of connections)
+//      HashMap<&str, (&str, Vec<&str>)>
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum ModuleType {
-    Broadcaster,
-    FlipFlop,
-    Conjunction,
-}
+pub type ModuleMap<'a> = HashMap<&'a str, (&'a str, Vec<&'a str>)>;
 
-pub type ModuleMap<'a> = HashMap<&'a str, (ModuleType, Vec<&'a str>)>;
+/// (module name, prefix, connections)
+pub type ModuleLine<'a> = (&'a str, &'a str, Vec<&'a str>);
 
-pub fn parse_line(input: &str) -> IResult<&str, (&str, ModuleType, Vec<&str>)> {
+pub fn parse_line<'a>(registry: &Registry<'_>, input: &'a str) -> IResult<&'a str, ModuleLine<'a>> {
     // Example input
     //
     // broadcaster -> a, b, c
@@ -34,37 +26,46 @@ pub fn parse_line(input: &str) -> IResult<&str, (&str, ModuleType, Vec<&str>)> {
     // %b -> c
     // %c -> inv
     // &inv -> a
+    //
+    // The prefix picks the module type, and has to be in the registry. Only broadcaster goes
+    // without one
 
-    let (input, modtype) =
-        map(
-            alt((tag("broadcaster"), tag("%"), tag("&"))),
-            |modtype| match modtype {
-                "broadcaster" => ModuleType::Broadcaster,
-                "%" => ModuleType::FlipFlop,
-                "&" => ModuleType::Conjunction,
-                _ => panic!("logic error"),
-            },
-        )(input)?;
-    let (input, module_name) = match modtype {
-        ModuleType::Broadcaster => (input, "broadcaster"),
-        _ => alpha1(input)?,
-    };
+    let (input, (prefix, module_name)) = verify(
+        tuple((
+            verify(take_till(|c: char| c.is_alphabetic()), |prefix: &str| {
+                registry.contains(prefix)
+            }),
+            alpha1,
+        )),
+        |&(prefix, name): &(&str, &str)| !prefix.is_empty() || name == "broadcaster",
+    )(input)?;
     let (input, _) = tuple((space1, tag("->"), space1))(input)?;
     let (input, connections) = separated_list1(tuple((tag(","), space0)), alpha1)(input)?;
 
-    Ok((input, (module_name, modtype, connections)))
+    Ok((input, (module_name, prefix, connections)))
 }
 
-pub fn parse(input: &str) -> IResult<&str, ModuleMap> {
-    let (input, lines) = all_consuming(terminated(
-        separated_list1(newline, parse_line),
+/// Parse every line, keeping them in order (and keeping any duplicate module names)
+pub fn parse_lines<'a>(
+    registry: &Registry<'_>,
+    input: &'a str,
+) -> IResult<&'a str, Vec<ModuleLine<'a>>> {
+    all_consuming(terminated(
+        separated_list1(newline, |input| parse_line(registry, input)),
         many0(newline),
-    ))(input)?;
+    ))(input)
+}
 
-    let mmap: ModuleMap = lines
+pub fn to_module_map(lines: Vec<ModuleLine<'_>>) -> ModuleMap<'_> {
+    lines
         .into_iter()
-        .map(|(src, modtype, dst)| (src, (modtype, dst)))
-        .collect();
+        .map(|(src, prefix, dst)| (src, (prefix, dst)))
+        .collect()
+}
 
-    Ok((input, mmap))
+/// Parse straight into a ModuleMap, without validating it
+#[cfg(test)]
+pub fn parse<'a>(registry: &Registry<'_>, input: &'a str) -> IResult<&'a str, ModuleMap<'a>> {
+    let (input, lines) = parse_lines(registry, input)?;
+    Ok((input, to_module_map(lines)))
 }
@@ -0,0 +1,249 @@
+use super::*;
+
+use module::StateError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Everything needed to carry on a simulation: how many times the button has been pressed, and the
+/// state of every module (as made by Module::to_json). E.g.
+///
+///     {"presses":1,"modules":{"a":{"on":false},"broadcaster":null,"inv":{"recent_pulses":[["a",false]]}}}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub presses: usize,
+    pub modules: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The snapshot has a module that isn't in the circuit
+    UnknownModule(String),
+    /// A module in the circuit isn't in the snapshot
+    MissingModule(String),
+    /// The module doesn't implement Module::to_json and Module::load_json
+    Unsupported(String),
+    /// The module's saved state doesn't fit the module
+    BadState {
+        module: String,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnknownModule(name) => {
+                write!(f, "snapshot has module {} which isn't in the input", name)
+            }
+            SnapshotError::MissingModule(name) => {
+                write!(f, "snapshot is missing module {}", name)
+            }
+            SnapshotError::Unsupported(name) => {
+                write!(f, "module {} does not support snapshots", name)
+            }
+            SnapshotError::BadState { module, error } => {
+                write!(f, "bad state for module {}: {}", module, error)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl SnapshotError {
+    fn from_state(module: &str, err: StateError) -> Self {
+        match err {
+            StateError::Unsupported => SnapshotError::Unsupported(module.to_owned()),
+            StateError::Json(error) => SnapshotError::BadState {
+                module: module.to_owned(),
+                error,
+            },
+        }
+    }
+}
+
+impl Snapshot {
+    /// Save every module's state. Fails if any module doesn't support snapshots
+    pub fn capture(state: &AllState<'_>, presses: usize) -> Result<Self, SnapshotError> {
+        let modules = state
+            .iter()
+            .map(|(name, module)| {
+                let json = module
+                    .to_json()
+                    .map_err(|err| SnapshotError::from_state(name, err))?;
+                Ok((name.to_string(), json))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Snapshot { presses, modules })
+    }
+
+    /// Load the snapshot into state, which has to have exactly the same modules. Returns the
+    /// number of presses the snapshot was taken after. If any module fails to load, state is left
+    /// as it was
+    pub fn restore(&self, state: &mut AllState<'_>) -> Result<usize, SnapshotError> {
+        if let Some(name) = self
+            .modules
+            .keys()
+            .find(|name| !state.contains_key(name.as_str()))
+        {
+            return Err(SnapshotError::UnknownModule(name.clone()));
+        }
+        // load into copies, and only replace the modules once they've all loaded
+        let mut loaded = Vec::with_capacity(state.len());
+        for (&name, module) in state.iter() {
+            let json = self
+                .modules
+                .get(name)
+                .ok_or_else(|| SnapshotError::MissingModule(name.to_string()))?;
+            let mut module = module.box_clone();
+            module
+                .load_json(json.clone())
+                .map_err(|err| SnapshotError::from_state(name, err))?;
+            loaded.push((name, module));
+        }
+        state.extend(loaded);
+        Ok(self.presses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_save_restore() {
+        let input = indoc! {"
+            broadcaster -> a, b, c
+            %a -> b
+            %b -> c
+            %c -> inv
+            &inv -> a
+        "};
+        let (mmap, initial) = circuit(input);
+        let connections = to_connections(&mmap);
+
+        let mut state = initial.clone();
+        for _ in 0..3 {
+            push_button(&mut state, &connections).for_each(drop);
+        }
+        let saved = serde_json::to_string(&Snapshot::capture(&state, 3).unwrap()).unwrap();
+
+        // resuming from the snapshot carries on exactly like the original
+        let mut resumed = initial.clone();
+        let snapshot: Snapshot = serde_json::from_str(&saved).unwrap();
+        assert_eq!(snapshot.restore(&mut resumed).unwrap(), 3);
+        assert_eq!(Snapshot::capture(&resumed, 3).unwrap(), snapshot);
+        for _ in 0..5 {
+            let pulses = |state| {
+                push_button(state, &connections)
+                    .map(|pulse| (pulse.src, pulse.dst, pulse.high_or_low))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(pulses(&mut state), pulses(&mut resumed));
+        }
+
+        let mut snapshot: Snapshot = serde_json::from_str(&saved).unwrap();
+        snapshot.modules.remove("b");
+        assert!(matches!(
+            snapshot.restore(&mut initial.clone()),
+            Err(SnapshotError::MissingModule(name)) if name == "b"
+        ));
+        snapshot
+            .modules
+            .insert("b".to_owned(), serde_json::json!({"on": 3}));
+        assert!(matches!(
+            snapshot.restore(&mut initial.clone()),
+            Err(SnapshotError::BadState { module, .. }) if module == "b"
+        ));
+        snapshot
+            .modules
+            .insert("b".to_owned(), serde_json::json!({"on": true}));
+        snapshot.modules.insert(
+            "inv".to_owned(),
+            serde_json::json!({"recent_pulses": [["b", true]]}),
+        );
+        assert!(matches!(
+            snapshot.restore(&mut initial.clone()),
+            Err(SnapshotError::BadState { module, .. }) if module == "inv"
+        ));
+        snapshot
+            .modules
+            .insert("zzz".to_owned(), serde_json::Value::Null);
+        assert!(matches!(
+            snapshot.restore(&mut initial.clone()),
+            Err(SnapshotError::UnknownModule(name)) if name == "zzz"
+        ));
+    }
+
+    #[test]
+    fn test_failed_restore() {
+        let input = indoc! {"
+            broadcaster -> a, b
+            %a -> inv
+            %b -> inv
+            &inv -> a
+        "};
+        let (mmap, mut state) = circuit(input);
+        let connections = to_connections(&mmap);
+        push_button(&mut state, &connections).for_each(drop);
+        let before = Snapshot::capture(&state, 1).unwrap();
+
+        // every module but b would load, and some of them before b
+        let mut snapshot = before.clone();
+        snapshot.presses = 0;
+        snapshot
+            .modules
+            .insert("a".to_owned(), serde_json::json!({"on": false}));
+        snapshot.modules.insert(
+            "inv".to_owned(),
+            serde_json::json!({"recent_pulses": [["a", false], ["b", false]]}),
+        );
+        snapshot
+            .modules
+            .insert("b".to_owned(), serde_json::json!({"on": "yes"}));
+        assert!(matches!(
+            snapshot.restore(&mut state),
+            Err(SnapshotError::BadState { module, .. }) if module == "b"
+        ));
+        assert_eq!(Snapshot::capture(&state, 1).unwrap(), before);
+    }
+
+    /// Passes pulses on, without implementing to_json or load_json
+    #[derive(Debug, Clone, Default)]
+    struct Opaque;
+
+    impl<'a> Module<'a> for Opaque {
+        fn receive(&mut self, _src: ModuleIdent<'a>, high_or_low: bool) -> Sends {
+            vec![(None, high_or_low)]
+        }
+
+        fn box_clone(&self) -> Box<dyn Module<'a> + 'a> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut registry = Registry::default();
+        registry.register("^", None, || Box::new(Opaque));
+        let (_, mmap) = parse(&registry, "broadcaster -> a\n^a -> b\n").unwrap();
+        let connections = to_connections(&mmap);
+        let mut state = init_state(&registry, &mmap, &connections);
+
+        assert!(matches!(
+            Snapshot::capture(&state, 0),
+            Err(SnapshotError::Unsupported(name)) if name == "a"
+        ));
+        let snapshot = Snapshot {
+            presses: 0,
+            modules: BTreeMap::from([
+                ("a".to_owned(), serde_json::Value::Null),
+                ("broadcaster".to_owned(), serde_json::Value::Null),
+            ]),
+        };
+        let err = snapshot.restore(&mut state).unwrap_err();
+        assert_eq!(err.to_string(), "module a does not support snapshots");
+    }
+}
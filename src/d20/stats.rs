@@ -0,0 +1,236 @@
+use super::*;
+
+use module::Kind;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ModuleStats<'a> {
+    pub module: ModuleIdent<'a>,
+    pub sent_low: usize,
+    pub sent_high: usize,
+    pub received_low: usize,
+    pub received_high: usize,
+    /// The first press on which a flip-flop got a low pulse (and so flipped). None for other
+    /// modules, or flip-flops that never flipped
+    pub first_flip: Option<usize>,
+    /// The smallest number of presses after which the pulses the module sends repeat, if there is
+    /// one that repeats at least twice in the presses looked at
+    pub period: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EdgeStats<'a> {
+    pub src: ModuleIdent<'a>,
+    pub dst: ModuleIdent<'a>,
+    pub low: usize,
+    pub high: usize,
+}
+
+/// Pulse counts for every module and edge over a number of presses. Modules and edges are sorted
+/// by name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report<'a> {
+    pub presses: usize,
+    pub modules: Vec<ModuleStats<'a>>,
+    pub edges: Vec<EdgeStats<'a>>,
+}
+
+/// Press the button presses times starting from state, and count everything. Module kinds are
+/// looked up in registry
+pub fn collect<'a>(
+    registry: &Registry<'_>,
+    mut state: AllState<'a>,
+    mmap: &ModuleMap<'a>,
+    connections: &Connections<'a, '_>,
+    presses: usize,
+) -> Report<'a> {
+    let mut modules: BTreeMap<ModuleIdent, ModuleStats> = BTreeMap::new();
+    let mut edges: BTreeMap<(ModuleIdent, ModuleIdent), EdgeStats> = BTreeMap::new();
+    // what each module sent on each press, as (low, high) counts. The period is found from these
+    let mut sent_per_press: BTreeMap<ModuleIdent, Vec<(usize, usize)>> = BTreeMap::new();
+
+    for press in 1..=presses {
+        for sent in sent_per_press.values_mut() {
+            sent.push((0, 0));
+        }
+        for pulse in push_button(&mut state, connections) {
+            let edge = edges
+                .entry((pulse.src, pulse.dst))
+                .or_insert_with(|| EdgeStats {
+                    src: pulse.src,
+                    dst: pulse.dst,
+                    ..Default::default()
+                });
+            let sent = sent_per_press
+                .entry(pulse.src)
+                .or_insert_with(|| vec![(0, 0); press]);
+            let (sent_low, sent_high) = sent.last_mut().unwrap();
+            if pulse.high_or_low {
+                edge.high += 1;
+                *sent_high += 1;
+            } else {
+                edge.low += 1;
+                *sent_low += 1;
+            }
+
+            for (name, is_src) in [(pulse.src, true), (pulse.dst, false)] {
+                let stats = modules.entry(name).or_insert_with(|| ModuleStats {
+                    module: name,
+                    ..Default::default()
+                });
+                match (is_src, pulse.high_or_low) {
+                    (true, false) => stats.sent_low += 1,
+                    (true, true) => stats.sent_high += 1,
+                    (false, false) => stats.received_low += 1,
+                    (false, true) => stats.received_high += 1,
+                }
+            }
+
+            let dst_is_flip_flop = mmap
+                .get(pulse.dst)
+                .is_some_and(|(prefix, _)| registry.kind(prefix) == Some(Kind::FlipFlop));
+            if dst_is_flip_flop && !pulse.high_or_low {
+                modules
+                    .get_mut(pulse.dst)
+                    .unwrap()
+                    .first_flip
+                    .get_or_insert(press);
+            }
+        }
+    }
+
+    for (name, sent) in &sent_per_press {
+        modules.get_mut(name).unwrap().period = period(sent);
+    }
+
+    Report {
+        presses,
+        modules: modules.into_values().collect(),
+        edges: edges.into_values().collect(),
+    }
+}
+
+/// The smallest p where xs[i] == xs[i + p] for every i, as long as xs has room for it to repeat
+fn period<T: PartialEq>(xs: &[T]) -> Option<usize> {
+    (1..=xs.len() / 2).find(|&p| xs.iter().zip(&xs[p..]).all(|(a, b)| a == b))
+}
+
+impl<'a> Report<'a> {
+    /// The module table then the edge table, separated by an empty line
+    pub fn to_csv(&self) -> String {
+        let optional = |val: Option<usize>| val.map(|val| val.to_string()).unwrap_or_default();
+        let mut out = String::new();
+        writeln!(
+            out,
+            "module,sent_low,sent_high,received_low,received_high,first_flip,period"
+        )
+        .unwrap();
+        for stats in &self.modules {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{}",
+                stats.module,
+                stats.sent_low,
+                stats.sent_high,
+                stats.received_low,
+                stats.received_high,
+                optional(stats.first_flip),
+                optional(stats.period)
+            )
+            .unwrap();
+        }
+        writeln!(out).unwrap();
+        writeln!(out, "src,dst,low,high").unwrap();
+        for edge in &self.edges {
+            writeln!(out, "{},{},{},{}", edge.src, edge.dst, edge.low, edge.high).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_period() {
+        assert_eq!(period(&[1, 2, 1, 2, 1]), Some(2));
+        assert_eq!(period(&[1, 1, 1]), Some(1));
+        assert_eq!(period(&[1, 2, 3]), None);
+        assert_eq!(period::<usize>(&[]), None);
+    }
+
+    #[test]
+    fn test_collect() {
+        let input = indoc! {"
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> output
+        "};
+        let registry = Registry::default();
+        let (mmap, state) = circuit(input);
+        let connections = to_connections(&mmap);
+        let report = collect(&registry, state, &mmap, &connections, 8);
+
+        let stats = |name| {
+            report
+                .modules
+                .iter()
+                .find(|stats| stats.module == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            stats("a"),
+            ModuleStats {
+                module: "a",
+                sent_low: 8,
+                sent_high: 8,
+                received_low: 8,
+                received_high: 0,
+                first_flip: Some(1),
+                period: Some(2),
+            }
+        );
+        // b only flips on presses where inv sends low, so it's half as fast as a
+        assert_eq!(stats("b").first_flip, Some(1));
+        assert_eq!(stats("b").period, Some(4));
+        assert_eq!(stats("inv").first_flip, None);
+        assert_eq!(stats("output").sent_low + stats("output").sent_high, 0);
+        assert_eq!(stats("output").period, None);
+
+        // the module totals agree with the edge totals
+        let (low, high) = report.edges.iter().fold((0, 0), |(low, high), edge| {
+            (low + edge.low, high + edge.high)
+        });
+        let button = stats("button");
+        assert_eq!(button.sent_low, 8);
+        assert_eq!(
+            report
+                .modules
+                .iter()
+                .map(|stats| stats.sent_low)
+                .sum::<usize>(),
+            low
+        );
+        assert_eq!(
+            report
+                .modules
+                .iter()
+                .map(|stats| stats.received_high)
+                .sum::<usize>(),
+            high
+        );
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with(
+            "module,sent_low,sent_high,received_low,received_high,first_flip,period\na,8,8,8,0,1,2\n"
+        ));
+        assert!(csv.contains("\n\nsrc,dst,low,high\na,con,4,4\n"));
+    }
+}
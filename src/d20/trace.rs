@@ -0,0 +1,215 @@
+use super::*;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// One pulse in a trace file. Traces are NDJSON, one of these per line. E.g.
+/// {"press":1,"seq":0,"src":"button","dst":"broadcaster","high":false}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry<'a> {
+    /// Which button press the pulse came from, starting at 1
+    pub press: usize,
+    /// The order of the pulse within its press, starting at 0
+    pub seq: usize,
+    pub src: &'a str,
+    pub dst: &'a str,
+    pub high: bool,
+}
+
+/// Press the button presses times, writing every pulse to out. first_press is the number of the
+/// first press (1 unless carrying on from a snapshot)
+pub fn record<'a>(
+    state: &mut AllState<'a>,
+    connections: &Connections<'a, '_>,
+    first_press: usize,
+    presses: usize,
+    mut out: impl Write,
+) -> io::Result<()> {
+    for press in first_press..first_press + presses {
+        for (seq, pulse) in push_button(state, connections).enumerate() {
+            let entry = TraceEntry {
+                press,
+                seq,
+                src: pulse.src,
+                dst: pulse.dst,
+                high: pulse.high_or_low,
+            };
+            serde_json::to_writer(&mut out, &entry)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+/// Read a trace written by record
+pub fn read_trace(content: &str) -> serde_json::Result<Vec<TraceEntry<'_>>> {
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Matches pulses by source, destination and level. Anything that's None matches everything
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PulseFilter<'a> {
+    pub src: Option<&'a str>,
+    pub dst: Option<&'a str>,
+    pub high: Option<bool>,
+}
+
+impl<'a> PulseFilter<'a> {
+    /// Parse a filter like src->dst:level, where level is high or low. * matches any module, and
+    /// the level can be left off. E.g. *->rx:low or qr->hub
+    pub fn parse(arg: &'a str) -> Result<Self, String> {
+        let err = || format!("expected a filter like src->dst:high but got {}", arg);
+        let (edge, high) = match arg.split_once(':') {
+            None => (arg, None),
+            Some((edge, "high")) => (edge, Some(true)),
+            Some((edge, "low")) => (edge, Some(false)),
+            Some(_) => return Err(err()),
+        };
+        let (src, dst) = edge.split_once("->").ok_or_else(err)?;
+        let module = |name| (name != "*").then_some(name);
+        Ok(PulseFilter {
+            src: module(src),
+            dst: module(dst),
+            high,
+        })
+    }
+
+    pub fn matches(&self, entry: &TraceEntry<'_>) -> bool {
+        self.src.is_none_or(|src| src == entry.src)
+            && self.dst.is_none_or(|dst| dst == entry.dst)
+            && self.high.is_none_or(|high| high == entry.high)
+    }
+}
+
+/// Every pulse sent or received by module
+pub fn pulses_for_module<'t, 'a>(
+    trace: &'t [TraceEntry<'a>],
+    module: &'t str,
+) -> impl Iterator<Item = &'t TraceEntry<'a>> + 't {
+    trace
+        .iter()
+        .filter(move |entry| entry.src == module || entry.dst == module)
+}
+
+/// The number of (low, high) pulses sent along each edge
+pub fn count_per_edge<'a>(
+    trace: &[TraceEntry<'a>],
+) -> BTreeMap<(&'a str, &'a str), (usize, usize)> {
+    let mut counts: BTreeMap<_, (usize, usize)> = BTreeMap::new();
+    for entry in trace {
+        let (low, high) = counts.entry((entry.src, entry.dst)).or_default();
+        if entry.high {
+            *high += 1;
+        } else {
+            *low += 1;
+        }
+    }
+    counts
+}
+
+/// The first press with a pulse that matches filter
+pub fn first_press(trace: &[TraceEntry<'_>], filter: &PulseFilter<'_>) -> Option<usize> {
+    trace
+        .iter()
+        .find(|entry| filter.matches(entry))
+        .map(|entry| entry.press)
+}
+
+/// Answer a query about a trace file without simulating anything. Queries are
+///      module NAME     every pulse to or from NAME
+///      edges           how many low and high pulses went along each edge
+///      first FILTER    the first press with a pulse matching FILTER (see PulseFilter::parse)
+pub fn replay(content: &str, query: &[String]) -> Result<String, Box<dyn Error>> {
+    let trace = read_trace(content)?;
+    let mut out = String::new();
+    match query {
+        [cmd, module] if cmd == "module" => {
+            for entry in pulses_for_module(&trace, module) {
+                out += &serde_json::to_string(entry)?;
+                out += "\n";
+            }
+        }
+        [cmd] if cmd == "edges" => {
+            for ((src, dst), (low, high)) in count_per_edge(&trace) {
+                out += &format!("{} -> {} low {} high {}\n", src, dst, low, high);
+            }
+        }
+        [cmd, filter] if cmd == "first" => {
+            match first_press(&trace, &PulseFilter::parse(filter)?) {
+                Some(press) => out += &format!("{}\n", press),
+                None => out += "never\n",
+            }
+        }
+        _ => return Err(format!("unknown query {:?}", query).into()),
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_record_and_replay() {
+        let input = indoc! {"
+            broadcaster -> a
+            %a -> inv, con
+            &inv -> b
+            %b -> con
+            &con -> output
+        "};
+        let (mmap, mut state) = circuit(input);
+        let connections = to_connections(&mmap);
+
+        let mut out = Vec::new();
+        record(&mut state, &connections, 1, 4, &mut out).unwrap();
+        let content = String::from_utf8(out).unwrap();
+        assert_eq!(
+            content.lines().next(),
+            Some(r#"{"press":1,"seq":0,"src":"button","dst":"broadcaster","high":false}"#)
+        );
+
+        let trace = read_trace(&content).unwrap();
+        // presses where a turns on have 8 pulses, and the ones where it turns off have 6
+        assert_eq!(trace.len(), 8 + 6 + 8 + 6);
+        // inv -> b every press, and b -> con every time b flips
+        assert_eq!(pulses_for_module(&trace, "b").count(), 4 + 2);
+        assert_eq!(
+            count_per_edge(&trace).get(&("a", "con")),
+            Some(&(2, 2)) // a turns on, off, on, off
+        );
+        assert_eq!(
+            first_press(&trace, &PulseFilter::parse("b->*:low").unwrap()),
+            Some(3)
+        );
+        assert_eq!(
+            first_press(&trace, &PulseFilter::parse("b->*").unwrap()),
+            Some(1)
+        );
+        assert_eq!(
+            first_press(&trace, &PulseFilter::parse("inv->b:high").unwrap()),
+            Some(2)
+        );
+        assert_eq!(
+            first_press(&trace, &PulseFilter::parse("*->b:high").unwrap()),
+            Some(2)
+        );
+        assert_eq!(
+            first_press(&trace, &PulseFilter::parse("con->a").unwrap()),
+            None
+        );
+        assert!(PulseFilter::parse("inv:b").is_err());
+
+        assert_eq!(
+            replay(&content, &["first".to_owned(), "*->output:low".to_owned()]).unwrap(),
+            "1\n"
+        );
+        assert!(replay(&content, &["nope".to_owned()]).is_err());
+    }
+}
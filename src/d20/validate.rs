@@ -0,0 +1,238 @@
+use super::*;
+
+use module::Kind;
+use parse::ModuleLine;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The circuit still runs, but probably not how it was meant to
+    Warning,
+    /// The circuit can't be simulated
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem with a module map, found before simulating it
+#[derive(Debug, PartialEq, Eq)]
+pub enum Issue<'a> {
+    /// There's no broadcaster for the button to send pulses to
+    MissingBroadcaster,
+    /// More than one line defines this module
+    DuplicateModule(ModuleIdent<'a>),
+    /// src sends pulses to dst, but dst isn't defined so the pulses go nowhere. Probably a typo,
+    /// since the sinks the puzzles watch (rx and output) aren't reported
+    UndefinedDestination {
+        src: ModuleIdent<'a>,
+        dst: ModuleIdent<'a>,
+    },
+    /// Nothing sends pulses to this conjunction, so it never does anything
+    ConjunctionWithoutInputs(ModuleIdent<'a>),
+    /// No pulse from the broadcaster can ever reach this module
+    Unreachable(ModuleIdent<'a>),
+}
+
+impl<'a> Issue<'a> {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::MissingBroadcaster | Issue::DuplicateModule(_) => Severity::Error,
+            Issue::UndefinedDestination { .. }
+            | Issue::ConjunctionWithoutInputs(_)
+            | Issue::Unreachable(_) => Severity::Warning,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Issue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingBroadcaster => write!(f, "there's no broadcaster module"),
+            Issue::DuplicateModule(name) => write!(f, "module {} is defined more than once", name),
+            Issue::UndefinedDestination { src, dst } => {
+                write!(f, "{} sends pulses to {}, which isn't defined", src, dst)
+            }
+            Issue::ConjunctionWithoutInputs(name) => {
+                write!(f, "conjunction {} has no inputs", name)
+            }
+            Issue::Unreachable(name) => {
+                write!(f, "module {} is unreachable from broadcaster", name)
+            }
+        }
+    }
+}
+
+/// Destinations that only receive pulses, for the puzzle to watch. They're never defined
+const SINKS: [ModuleIdent; 2] = ["rx", "output"];
+
+/// Check the lines of an input for problems. Issues are in the order of the lines they're found on,
+/// with unreachable modules last. Module kinds are looked up in registry
+pub fn validate<'a>(registry: &Registry<'_>, lines: &[ModuleLine<'a>]) -> Vec<Issue<'a>> {
+    let kind = |prefix: &str| registry.kind(prefix);
+
+    let mut issues = Vec::new();
+
+    if !lines
+        .iter()
+        .any(|&(name, prefix, _)| name == "broadcaster" && kind(prefix) == Some(Kind::Broadcaster))
+    {
+        issues.push(Issue::MissingBroadcaster);
+    }
+
+    let mut defined = HashSet::new();
+    for &(name, _, _) in lines {
+        if !defined.insert(name) {
+            issues.push(Issue::DuplicateModule(name));
+        }
+    }
+
+    let has_inputs: HashSet<ModuleIdent> = lines
+        .iter()
+        .flat_map(|(_, _, dsts)| dsts.iter().copied())
+        .collect();
+    for (name, prefix, dsts) in lines {
+        for dst in dsts
+            .iter()
+            .filter(|dst| !defined.contains(*dst) && !SINKS.contains(*dst))
+        {
+            issues.push(Issue::UndefinedDestination { src: name, dst });
+        }
+        if kind(prefix) == Some(Kind::Conjunction) && !has_inputs.contains(name) {
+            issues.push(Issue::ConjunctionWithoutInputs(name));
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::from(["broadcaster"]);
+    while let Some(name) = queue.pop_front() {
+        if reachable.insert(name) {
+            for (_, _, dsts) in lines.iter().filter(|(other, _, _)| *other == name) {
+                queue.extend(dsts.iter().copied());
+            }
+        }
+    }
+    // duplicates are only reported once
+    let mut reported = HashSet::new();
+    issues.extend(
+        lines
+            .iter()
+            .map(|&(name, _, _)| name)
+            .filter(|name| !reachable.contains(name) && reported.insert(*name))
+            .map(Issue::Unreachable),
+    );
+
+    issues
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+
+    fn check(input: &str) -> Vec<Issue<'_>> {
+        let registry = Registry::default();
+        let (_, lines) = parse::parse_lines(&registry, input).unwrap();
+        validate(&registry, &lines)
+    }
+
+    #[test]
+    fn test_valid() {
+        let input = indoc! {"
+            broadcaster -> a, b, c
+            %a -> b
+            %b -> c
+            %c -> inv
+            &inv -> a
+        "};
+        assert_eq!(check(input), vec![]);
+    }
+
+    #[test]
+    fn test_issues() {
+        let input = indoc! {"
+            %a -> b, c
+            &b -> a
+            &lonely -> a
+            %a -> b
+        "};
+        let issues = check(input);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::MissingBroadcaster,
+                Issue::DuplicateModule("a"),
+                Issue::UndefinedDestination { src: "a", dst: "c" },
+                Issue::ConjunctionWithoutInputs("lonely"),
+                Issue::Unreachable("a"),
+                Issue::Unreachable("b"),
+                Issue::Unreachable("lonely"),
+            ]
+        );
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.severity())
+                .collect::<Vec<_>>(),
+            vec![
+                Severity::Error,
+                Severity::Error,
+                Severity::Warning,
+                Severity::Warning,
+                Severity::Warning,
+                Severity::Warning,
+                Severity::Warning,
+            ]
+        );
+        assert_eq!(
+            issues[2].to_string(),
+            "a sends pulses to c, which isn't defined"
+        );
+    }
+
+    #[test]
+    fn test_registered_kinds() {
+        let mut registry = Registry::default();
+        registry.register("^", Some(Kind::Conjunction), || {
+            Box::<module::Conjunction>::default()
+        });
+        let (_, lines) =
+            parse::parse_lines(&registry, "broadcaster -> a\n^a -> a\n^b -> a\n").unwrap();
+        assert_eq!(
+            validate(&registry, &lines),
+            vec![
+                Issue::ConjunctionWithoutInputs("b"),
+                Issue::Unreachable("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let input = indoc! {"
+            broadcaster -> a
+            %a -> output
+            %b -> a
+        "};
+        assert_eq!(check(input), vec![Issue::Unreachable("b")]);
+    }
+
+    #[test]
+    fn test_sinks() {
+        // the part 2 shape: a conjunction collecting cycles and sending to rx
+        let input = indoc! {"
+            broadcaster -> a, b
+            %a -> xx
+            %b -> xx, output
+            &xx -> rx
+        "};
+        assert_eq!(check(input), vec![]);
+    }
+}
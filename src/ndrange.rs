@@ -0,0 +1,627 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::{Add, Mul, Sub};
+
+/// The integer types an Interval can be built over. Implemented for all the primitive signed and
+/// unsigned integers
+pub trait Int:
+    Copy + Ord + Debug + Hash + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Int for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// A 1D half open interval [start, end). Empty when start == end
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Interval<T> {
+    pub start: T,
+    pub end: T,
+}
+
+impl<T: Int> Interval<T> {
+    pub fn new(start: T, end: T) -> Self {
+        debug_assert!(end >= start, "start {:?} is not <= end {:?}", start, end);
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> T {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, val: T) -> bool {
+        self.start <= val && val < self.end
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        !((self.end <= other.start) || (other.end <= self.start))
+    }
+
+    /// Clamp val to lie within [start, end]
+    pub fn clamp_point(&self, val: T) -> T {
+        val.clamp(self.start, self.end)
+    }
+
+    /// Split into [start, val) and [val, end). Either half may be empty if val is out of bounds
+    pub fn split(&self, val: T) -> (Self, Self) {
+        let split_point = self.clamp_point(val);
+        (
+            Interval::new(self.start, split_point),
+            Interval::new(split_point, self.end),
+        )
+    }
+
+    /// The part of self that is also in other. Empty (but still within self) if they don't overlap
+    pub fn intersection(&self, other: &Self) -> Self {
+        let start = self.clamp_point(other.start);
+        let end = self.clamp_point(other.end).max(start);
+        Interval::new(start, end)
+    }
+
+    pub fn debug_assert_disjoint(intervals: &[Self]) {
+        let mut intervals = intervals.to_vec();
+        intervals.sort();
+        for window in intervals.windows(2) {
+            let (interval1, interval2) = (&window[0], &window[1]);
+            debug_assert!(
+                interval1.end <= interval2.start,
+                "intervals are not disjoint {:?} {:?}",
+                interval1,
+                interval2
+            );
+        }
+    }
+}
+
+/// A set of values, kept as sorted intervals that neither overlap nor touch. Intervals that would
+/// are merged as they're inserted, so the set never holds more intervals than it has to
+#[derive(Debug, PartialEq, Eq, Clone, Default, Hash)]
+pub struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+impl<T: Int> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet { intervals: vec![] }
+    }
+
+    /// The number of intervals, not values
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn contains(&self, val: T) -> bool {
+        let idx = self
+            .intervals
+            .partition_point(|interval| interval.end <= val);
+        self.intervals
+            .get(idx)
+            .is_some_and(|interval| interval.contains(val))
+    }
+
+    /// Add interval, merging it with any intervals it overlaps or touches
+    pub fn insert(&mut self, interval: Interval<T>) {
+        if interval.is_empty() {
+            return;
+        }
+        // intervals[first..last] are the ones to merge with
+        let first = self
+            .intervals
+            .partition_point(|other| other.end < interval.start);
+        let last = self
+            .intervals
+            .partition_point(|other| other.start <= interval.end);
+        let merged = if first < last {
+            Interval::new(
+                interval.start.min(self.intervals[first].start),
+                interval.end.max(self.intervals[last - 1].end),
+            )
+        } else {
+            interval
+        };
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    /// The intervals in order
+    pub fn iter(&self) -> std::slice::Iter<'_, Interval<T>> {
+        self.intervals.iter()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = self.clone();
+        for &interval in other {
+            union.insert(interval);
+        }
+        union
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        // neither set has touching intervals, so neither can the overlaps
+        let mut intervals = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (&self.intervals[i], &other.intervals[j]);
+            let overlap = a.intersection(b);
+            if !overlap.is_empty() {
+                intervals.push(overlap);
+            }
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// The values in self but not other
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut intervals = vec![];
+        for interval in &self.intervals {
+            let mut start = interval.start;
+            let first = other
+                .intervals
+                .partition_point(|other| other.end <= interval.start);
+            for cut in other.intervals[first..]
+                .iter()
+                .take_while(|cut| cut.start < interval.end)
+            {
+                if start < cut.start {
+                    intervals.push(Interval::new(start, cut.start));
+                }
+                start = start.max(cut.end);
+            }
+            if start < interval.end {
+                intervals.push(Interval::new(start, interval.end));
+            }
+        }
+        IntervalSet { intervals }
+    }
+}
+
+impl<T: Int> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        let mut set = IntervalSet::new();
+        for interval in iter {
+            set.insert(interval);
+        }
+        set
+    }
+}
+
+impl<T> IntoIterator for IntervalSet<T> {
+    type Item = Interval<T>;
+    type IntoIter = std::vec::IntoIter<Interval<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.intervals.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a IntervalSet<T> {
+    type Item = &'a Interval<T>;
+    type IntoIter = std::slice::Iter<'a, Interval<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.intervals.iter()
+    }
+}
+
+/// An NDRange ( [start1, end1), [start2, end2) ...). Forms an n-dimensional box
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct NDRange<T, const N: usize>(pub [Interval<T>; N]);
+
+impl<T: Int, const N: usize> NDRange<T, N> {
+    pub fn new(data: [Interval<T>; N]) -> Self {
+        NDRange(data)
+    }
+
+    /// A box is empty if it's empty along any dimension
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().any(Interval::is_empty)
+    }
+
+    pub fn split(&self, dimension: usize, val: T) -> (Self, Self) {
+        let mut left = *self;
+        let mut right = *self;
+        let (left_range, right_range) = self.0[dimension].split(val);
+        left.0[dimension] = left_range;
+        right.0[dimension] = right_range;
+        (left, right)
+    }
+
+    pub fn volume(&self) -> T {
+        self.0.iter().fold(T::ONE, |acc, range| acc * range.len())
+    }
+
+    pub fn contains_point(&self, point: &[T; N]) -> bool {
+        self.0
+            .iter()
+            .zip(point)
+            .all(|(range, &val)| range.contains(val))
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(r1, r2)| r1.overlaps(r2))
+    }
+
+    /// The box of points in both self and other. Possibly empty
+    pub fn intersect(&self, other: &Self) -> Self {
+        let mut result = *self;
+        for (range, other_range) in result.0.iter_mut().zip(other.0.iter()) {
+            *range = range.intersection(other_range);
+        }
+        result
+    }
+
+    /// The points in self but not in other, as a list of disjoint non-empty boxes
+    pub fn subtract(&self, other: &Self) -> Vec<Self> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        if !self.overlaps(other) {
+            return vec![*self];
+        }
+
+        // Peel off the slabs below and above other one dimension at a time. Whatever is left at
+        // the end is self.intersect(other)
+        let mut result = Vec::new();
+        let mut remain = *self;
+        for dimension in 0..N {
+            let (below, rest) = remain.split(dimension, other.0[dimension].start);
+            let (middle, above) = rest.split(dimension, other.0[dimension].end);
+            result.extend([below, above].into_iter().filter(|r| !r.is_empty()));
+            remain = middle;
+        }
+        result
+    }
+
+    /// Combine a bunch of (possibly overlapping) boxes into a normalised set of disjoint non-empty
+    /// boxes covering the same points. Boxes that share a face are merged where possible
+    pub fn union(ranges: impl IntoIterator<Item = Self>) -> Vec<Self> {
+        let mut result: Vec<Self> = Vec::new();
+        for range in ranges {
+            let mut fragments = vec![range];
+            for existing in &result {
+                fragments = fragments
+                    .iter()
+                    .flat_map(|fragment| fragment.subtract(existing))
+                    .collect();
+            }
+            result.extend(fragments.into_iter().filter(|r| !r.is_empty()));
+        }
+        Self::merge_adjacent(&mut result);
+        result.sort_by_key(|r| r.0);
+        result
+    }
+
+    /// If self and other are the same along every dimension but one, and touch along that one,
+    /// return the single box covering both
+    pub fn merge(&self, other: &Self) -> Option<Self> {
+        let mut differing = (0..N).filter(|&d| self.0[d] != other.0[d]);
+        let dimension = match (differing.next(), differing.next()) {
+            (Some(dimension), None) => dimension,
+            (None, _) => return Some(*self),
+            (Some(_), Some(_)) => return None,
+        };
+        let (r1, r2) = (self.0[dimension], other.0[dimension]);
+        if r1.end != r2.start && r2.end != r1.start {
+            return None;
+        }
+        let mut merged = *self;
+        merged.0[dimension] = Interval::new(r1.start.min(r2.start), r1.end.max(r2.end));
+        Some(merged)
+    }
+
+    fn merge_adjacent(ranges: &mut Vec<Self>) {
+        'outer: loop {
+            for i in 0..ranges.len() {
+                for j in (i + 1)..ranges.len() {
+                    if let Some(merged) = ranges[i].merge(&ranges[j]) {
+                        ranges[i] = merged;
+                        ranges.swap_remove(j);
+                        continue 'outer;
+                    }
+                }
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interval() {
+        let interval = Interval::new(-5i32, 5);
+        assert_eq!(interval.len(), 10);
+        assert!(interval.contains(-5));
+        assert!(!interval.contains(5));
+        assert!(interval.overlaps(&Interval::new(4, 8)));
+        assert!(!interval.overlaps(&Interval::new(5, 8)));
+        assert_eq!(interval.clamp_point(-10), -5);
+        assert_eq!(interval.clamp_point(10), 5);
+        assert_eq!(
+            interval.intersection(&Interval::new(0, 8)),
+            Interval::new(0, 5)
+        );
+        assert!(interval.intersection(&Interval::new(6, 8)).is_empty());
+        assert!(interval.intersection(&Interval::new(-8, -6)).is_empty());
+    }
+
+    #[test]
+    fn test_interval_set_insert() {
+        let mut set = IntervalSet::new();
+        set.insert(Interval::new(10u64, 20));
+        set.insert(Interval::new(30, 40));
+        set.insert(Interval::new(0, 5));
+        set.insert(Interval::new(7, 7));
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![
+                Interval::new(0, 5),
+                Interval::new(10, 20),
+                Interval::new(30, 40)
+            ]
+        );
+
+        // touching intervals merge as well as overlapping ones
+        set.insert(Interval::new(5, 10));
+        assert_eq!(set.len(), 2);
+        set.insert(Interval::new(15, 35));
+        assert_eq!(
+            set.into_iter().collect::<Vec<_>>(),
+            vec![Interval::new(0, 40)]
+        );
+
+        let set: IntervalSet<i32> = [
+            Interval::new(3, 4),
+            Interval::new(1, 2),
+            Interval::new(2, 3),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&Interval::new(1, 4)]);
+        assert!(set.contains(1));
+        assert!(set.contains(3));
+        assert!(!set.contains(4));
+        assert!(!set.contains(0));
+    }
+
+    #[test]
+    fn test_interval_set_operations() {
+        let a: IntervalSet<u32> = [Interval::new(0, 10), Interval::new(20, 30)]
+            .into_iter()
+            .collect();
+        let b: IntervalSet<u32> = [Interval::new(5, 25), Interval::new(28, 40)]
+            .into_iter()
+            .collect();
+        let set = |intervals: &[(u32, u32)]| -> IntervalSet<u32> {
+            intervals
+                .iter()
+                .map(|&(start, end)| Interval::new(start, end))
+                .collect()
+        };
+
+        assert_eq!(a.union(&b), set(&[(0, 40)]));
+        assert_eq!(a.intersection(&b), set(&[(5, 10), (20, 25), (28, 30)]));
+        assert_eq!(a.difference(&b), set(&[(0, 5), (25, 28)]));
+        assert_eq!(b.difference(&a), set(&[(10, 20), (30, 40)]));
+        assert_eq!(a.difference(&a), IntervalSet::new());
+        assert_eq!(a.intersection(&IntervalSet::new()), IntervalSet::new());
+
+        // every value is in exactly the sets it should be
+        for val in 0..45 {
+            let (in_a, in_b) = (a.contains(val), b.contains(val));
+            assert_eq!(a.union(&b).contains(val), in_a || in_b);
+            assert_eq!(a.intersection(&b).contains(val), in_a && in_b);
+            assert_eq!(a.difference(&b).contains(val), in_a && !in_b);
+        }
+    }
+
+    #[test]
+    fn test_volume_and_contains_point() {
+        let ndrange = NDRange::new([Interval::new(-2i32, 2), Interval::new(0, 3)]);
+        assert_eq!(ndrange.volume(), 12);
+        assert!(ndrange.contains_point(&[-2, 0]));
+        assert!(!ndrange.contains_point(&[2, 0]));
+        assert!(!ndrange.contains_point(&[0, 3]));
+        assert!(NDRange::new([Interval::new(0u64, 5), Interval::new(3, 3)]).is_empty());
+    }
+
+    #[test]
+    fn test_intersect() {
+        let a = NDRange::new([Interval::new(0usize, 10), Interval::new(0, 10)]);
+        let b = NDRange::new([Interval::new(5usize, 15), Interval::new(0, 3)]);
+        assert_eq!(
+            a.intersect(&b),
+            NDRange::new([Interval::new(5, 10), Interval::new(0, 3)])
+        );
+        let c = NDRange::new([Interval::new(20usize, 30), Interval::new(0, 10)]);
+        assert!(a.intersect(&c).is_empty());
+    }
+
+    #[test]
+    fn test_subtract() {
+        let a = NDRange::new([
+            Interval::new(0i64, 10),
+            Interval::new(0, 10),
+            Interval::new(0, 10),
+        ]);
+        let b = NDRange::new([
+            Interval::new(2i64, 4),
+            Interval::new(-5, 5),
+            Interval::new(3, 20),
+        ]);
+        let diff = a.subtract(&b);
+        assert_eq!(
+            diff.iter().map(NDRange::volume).sum::<i64>(),
+            a.volume() - a.intersect(&b).volume()
+        );
+        for (i, r1) in diff.iter().enumerate() {
+            assert!(!r1.overlaps(&b));
+            for r2 in &diff[i + 1..] {
+                assert!(!r1.overlaps(r2));
+            }
+        }
+
+        // disjoint boxes are unchanged, and subtracting a superset leaves nothing
+        let c = NDRange::new([
+            Interval::new(20i64, 30),
+            Interval::new(0, 10),
+            Interval::new(0, 10),
+        ]);
+        assert_eq!(a.subtract(&c), vec![a]);
+        assert_eq!(b.intersect(&a).subtract(&a), vec![]);
+    }
+
+    #[test]
+    fn test_union() {
+        let a = NDRange::new([Interval::new(0u32, 10), Interval::new(0, 10)]);
+        let b = NDRange::new([Interval::new(5u32, 15), Interval::new(5, 15)]);
+        let union = NDRange::union([a, b, a]);
+        assert_eq!(union.iter().map(NDRange::volume).sum::<u32>(), 175);
+        for (i, r1) in union.iter().enumerate() {
+            for r2 in &union[i + 1..] {
+                assert!(!r1.overlaps(r2));
+            }
+        }
+
+        // boxes sharing a face merge back into one
+        let left = NDRange::new([Interval::new(0u32, 5), Interval::new(0, 10)]);
+        let right = NDRange::new([Interval::new(5u32, 10), Interval::new(0, 10)]);
+        assert_eq!(NDRange::union([left, right]), vec![a]);
+    }
+
+    #[test]
+    fn test_split_range() {
+        let range = Interval::new(1usize, 10);
+        assert_eq!(range.split(0), (Interval::new(1, 1), Interval::new(1, 10)));
+        assert_eq!(range.split(1), (Interval::new(1, 1), Interval::new(1, 10)));
+        assert_eq!(range.split(5), (Interval::new(1, 5), Interval::new(5, 10)));
+        assert_eq!(
+            range.split(10),
+            (Interval::new(1, 10), Interval::new(10, 10))
+        );
+        assert_eq!(
+            range.split(11),
+            (Interval::new(1, 10), Interval::new(10, 10))
+        );
+    }
+
+    #[test]
+    fn test_split_ndrange() {
+        let ndrange = NDRange::new([
+            Interval::new(1usize, 10),
+            Interval::new(1, 10),
+            Interval::new(1, 10),
+            Interval::new(1, 10),
+        ]);
+
+        assert_eq!(
+            ndrange.split(0, 0),
+            (
+                NDRange::new([
+                    Interval::new(1, 1),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ]),
+                NDRange::new([
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ])
+            )
+        );
+
+        assert_eq!(
+            ndrange.split(0, 5),
+            (
+                NDRange::new([
+                    Interval::new(1, 5),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ]),
+                NDRange::new([
+                    Interval::new(5, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ])
+            )
+        );
+
+        assert_eq!(
+            ndrange.split(0, 10),
+            (
+                NDRange::new([
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ]),
+                NDRange::new([
+                    Interval::new(10, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ])
+            )
+        );
+
+        assert_eq!(
+            ndrange.split(1, 5),
+            (
+                NDRange::new([
+                    Interval::new(1, 10),
+                    Interval::new(1, 5),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ]),
+                NDRange::new([
+                    Interval::new(1, 10),
+                    Interval::new(5, 10),
+                    Interval::new(1, 10),
+                    Interval::new(1, 10),
+                ])
+            )
+        );
+
+        let signed = NDRange::new([Interval::new(-10i64, 10), Interval::new(-10, 10)]);
+        assert_eq!(
+            signed.split(1, -3),
+            (
+                NDRange::new([Interval::new(-10, 10), Interval::new(-10, -3)]),
+                NDRange::new([Interval::new(-10, 10), Interval::new(-3, 10)]),
+            )
+        );
+    }
+}